@@ -0,0 +1 @@
+ALTER TABLE records ADD COLUMN status TEXT NOT NULL DEFAULT 'complete';
@@ -0,0 +1,11 @@
+CREATE TABLE record_chunks (
+  record_id VARCHAR(50) NOT NULL REFERENCES records(id) ON DELETE CASCADE,
+  chunk_id INTEGER NOT NULL,
+
+  duration INTEGER,
+  received_time TEXT,
+  key_frame_id INTEGER,
+  next_chunk_id INTEGER,
+
+  PRIMARY KEY(record_id, chunk_id)
+);
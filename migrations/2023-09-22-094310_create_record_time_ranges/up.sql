@@ -0,0 +1,10 @@
+CREATE TABLE record_time_ranges (
+  record_id VARCHAR(50) NOT NULL REFERENCES records(id) ON DELETE CASCADE,
+  kind TEXT NOT NULL,
+  media_id INTEGER NOT NULL,
+
+  start_time REAL NOT NULL,
+  end_time REAL NOT NULL,
+
+  PRIMARY KEY(record_id, kind, media_id)
+);
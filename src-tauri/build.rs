@@ -1,3 +1,6 @@
 fn main() {
-    tauri_build::build()
+    // The headless command-line binary is built without Tauri
+    if std::env::var_os("CARGO_FEATURE_DESKTOP").is_some() {
+        tauri_build::build()
+    }
 }
@@ -0,0 +1,35 @@
+use crate::db::DbError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("IO error occurred: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid archive header: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("could not access the database: {0}")]
+    Database(#[from] DbError),
+
+    #[error("not a pyke-director archive")]
+    InvalidFormat,
+
+    #[error("archive is truncated or corrupt")]
+    Corrupt,
+
+    #[error("unsupported archive format version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("invalid {0} in archive header")]
+    InvalidId(&'static str),
+
+    #[error("no record for game {0}")]
+    RecordNotFound(String),
+
+    #[error("game {0} is already in the library")]
+    AlreadyImported(String),
+
+    #[error("game {0} is still being recorded")]
+    StillRecording(String),
+}
@@ -0,0 +1,543 @@
+pub mod error;
+pub mod storage;
+
+use crate::decoder::time_index;
+use crate::models::record::{Record, RecordStatus};
+use crate::models::record_chunk::RecordChunk;
+use crate::queries;
+use error::ArchiveError;
+
+use chrono::NaiveDateTime;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// An archive starts with this magic, the format version and the length of
+/// the header as little endian u32, then the JSON header and the payloads.
+const MAGIC: &[u8; 8] = b"PYKEDIR\0";
+const FORMAT_VERSION: u32 = 1;
+const PREAMBLE_LENGTH: u64 = 16;
+
+/// Position of a file in the payload section, which follows the header.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct Payload {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl Payload {
+    /// Whether the payload ends within a payload section of `length` bytes.
+    fn fits_in(&self, length: u64) -> bool {
+        self.offset
+            .checked_add(self.length)
+            .is_some_and(|end| end <= length)
+    }
+}
+
+/// Everything the database knows about a recorded game and the index of its
+/// chunk, keyframe and end of game stats payloads.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ArchiveHeader {
+    pub version: String,
+    pub base_url: String,
+    pub platform_id: String,
+    pub game_id: String,
+    pub encryption_key: String,
+    pub status: String,
+    /// Why the recording failed, archives written before it existed have none.
+    #[serde(default)]
+    pub status_reason: Option<String>,
+    pub created_at: NaiveDateTime,
+    /// Game metadata as returned by the spectator API.
+    pub metadata: serde_json::Value,
+    pub chunks: Vec<RecordChunk>,
+    pub game_data_chunks: BTreeMap<u32, Payload>,
+    pub keyframes: BTreeMap<u32, Payload>,
+    pub end_of_game_stats: Option<Payload>,
+}
+
+/// An archive opened for reading, only its header is kept in memory.
+pub struct Archive {
+    pub header: ArchiveHeader,
+    path: PathBuf,
+    payload_start: u64,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Self, ArchiveError> {
+        let file = File::open(path)?;
+        let file_length = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ArchiveError::InvalidFormat);
+        }
+
+        let format_version = read_u32(&mut reader)?;
+        if format_version != FORMAT_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(format_version));
+        }
+
+        // Lengths are checked against the file before allocating anything
+        let header_length = read_u32(&mut reader)?;
+        let payload_start = PREAMBLE_LENGTH + header_length as u64;
+        if payload_start > file_length {
+            return Err(ArchiveError::Corrupt);
+        }
+        let mut header = vec![0; header_length as usize];
+        reader.read_exact(&mut header)?;
+
+        let archive = Archive {
+            header: serde_json::from_slice(&header)?,
+            path: path.to_path_buf(),
+            payload_start,
+        };
+        let payloads_length = file_length - payload_start;
+        if !archive
+            .payloads()
+            .all(|payload| payload.fits_in(payloads_length))
+        {
+            return Err(ArchiveError::Corrupt);
+        }
+
+        Ok(archive)
+    }
+
+    fn payloads(&self) -> impl Iterator<Item = &Payload> {
+        let header = &self.header;
+
+        header
+            .game_data_chunks
+            .values()
+            .chain(header.keyframes.values())
+            .chain(&header.end_of_game_stats)
+    }
+
+    pub fn read_payload(&self, payload: &Payload) -> Result<Vec<u8>, ArchiveError> {
+        let mut file = File::open(&self.path)?;
+        file.seek(SeekFrom::Start(self.payload_start + payload.offset))?;
+
+        let mut data = vec![0; payload.length as usize];
+        file.read_exact(&mut data)?;
+
+        Ok(data)
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, io::Error> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Files going into the payload section, in order.
+#[derive(Default)]
+struct PayloadFiles {
+    files: Vec<(PathBuf, Payload)>,
+    length: u64,
+}
+
+impl PayloadFiles {
+    fn add(&mut self, path: PathBuf) -> Result<Payload, io::Error> {
+        let length = fs::metadata(&path)?.len();
+        let payload = Payload {
+            offset: self.length,
+            length,
+        };
+
+        self.files.push((path, payload));
+        self.length += length;
+
+        Ok(payload)
+    }
+
+    fn add_all(
+        &mut self,
+        dir: &Path,
+        ids: impl IntoIterator<Item = u32>,
+    ) -> Result<BTreeMap<u32, Payload>, io::Error> {
+        let ids: BTreeSet<u32> = ids.into_iter().collect();
+
+        ids.into_iter()
+            .map(|id| Ok((id, self.add(dir.join(id.to_string()))?)))
+            .collect()
+    }
+}
+
+/// Pack a game of the library and its files into a single archive at `path`.
+pub fn export_record(game_id: &str, path: &Path) -> Result<(), ArchiveError> {
+    let record = queries::get_record(game_id.to_string())?
+        .ok_or_else(|| ArchiveError::RecordNotFound(game_id.to_string()))?;
+
+    // A packed game is already stored as an archive
+    if Path::new(&record.storage_path).is_file() {
+        fs::copy(&record.storage_path, path)?;
+        return Ok(());
+    }
+
+    let chunks = queries::get_record_chunks(&record.id)?;
+    write_archive(&record, chunks, path)
+}
+
+fn write_archive(
+    record: &Record,
+    chunks: Vec<RecordChunk>,
+    path: &Path,
+) -> Result<(), ArchiveError> {
+    let storage_path = Path::new(&record.storage_path);
+    let mut payload_files = PayloadFiles::default();
+
+    let game_data_chunks = payload_files.add_all(
+        &storage_path.join("game_data_chunks"),
+        record.game_data_chunk_ids(),
+    )?;
+    let keyframes =
+        payload_files.add_all(&storage_path.join("keyframes"), record.keyframe_ids())?;
+    let end_of_game_stats_path = storage_path.join("end_of_game_stats");
+    let end_of_game_stats = if end_of_game_stats_path.is_file() {
+        Some(payload_files.add(end_of_game_stats_path)?)
+    } else {
+        None
+    };
+
+    let header = serde_json::to_vec(&ArchiveHeader {
+        version: record.version.clone(),
+        base_url: record.base_url.clone(),
+        platform_id: record.platform_id.clone(),
+        game_id: record.game_id.clone(),
+        encryption_key: record.encryption_key.clone(),
+        status: record.status.clone(),
+        status_reason: record.status_reason.clone(),
+        created_at: record.created_at,
+        metadata: serde_json::from_str(&record.metadata)?,
+        chunks,
+        game_data_chunks,
+        keyframes,
+        end_of_game_stats,
+    })?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&(header.len() as u32).to_le_bytes())?;
+    writer.write_all(&header)?;
+
+    for (file_path, payload) in payload_files.files {
+        let copied = io::copy(
+            &mut File::open(&file_path)?.take(payload.length),
+            &mut writer,
+        )?;
+        // The index is already written, a file that shrank since would shift
+        // every following payload
+        if copied != payload.length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} changed during the export", file_path.display()),
+            )
+            .into());
+        }
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Add the game of an archive to the library. With `extract` its files are
+/// unpacked into `storage_path`, otherwise the archive is copied there and
+/// served as is.
+pub fn import_record(
+    path: &Path,
+    storage_path: &Path,
+    extract: bool,
+) -> Result<Record, ArchiveError> {
+    let archive = Archive::open(path)?;
+    let header = &archive.header;
+
+    // Both ids name the record directory, they must not reach outside of it
+    if !is_valid_id(&header.platform_id) {
+        return Err(ArchiveError::InvalidId("platform id"));
+    }
+    if !is_valid_id(&header.game_id) {
+        return Err(ArchiveError::InvalidId("game id"));
+    }
+    if queries::get_record(header.game_id.clone())?.is_some() {
+        return Err(ArchiveError::AlreadyImported(header.game_id.clone()));
+    }
+
+    let record_path = storage_path.join(format!("{}_{}", header.platform_id, header.game_id));
+    let record_path = if extract {
+        extract_files(&archive, &record_path)?;
+        record_path
+    } else {
+        let archive_path = archive_path(&record_path);
+        fs::create_dir_all(storage_path)?;
+        fs::copy(path, &archive_path)?;
+        archive_path
+    };
+
+    // An unfinished recording must not be resumed by this install
+    let status = match header.status.parse() {
+        Ok(RecordStatus::Recording) => RecordStatus::Cancelled.to_string(),
+        _ => header.status.clone(),
+    };
+    let record = Record {
+        id: Uuid::new_v4().to_string(),
+        version: header.version.clone(),
+        base_url: header.base_url.clone(),
+        platform_id: header.platform_id.clone(),
+        game_id: header.game_id.clone(),
+        encryption_key: header.encryption_key.clone(),
+        metadata: header.metadata.to_string(),
+        keyframes: serde_json::to_string(&header.keyframes.keys().collect::<Vec<_>>())?,
+        game_data_chunks: serde_json::to_string(
+            &header.game_data_chunks.keys().collect::<Vec<_>>(),
+        )?,
+        storage_path: record_path.display().to_string(),
+        created_at: header.created_at,
+        status,
+        status_reason: header.status_reason.clone(),
+    };
+
+    let record_id = queries::save_record(&record)?;
+    queries::save_record_chunks(
+        header
+            .chunks
+            .iter()
+            .map(|chunk| RecordChunk {
+                record_id: record_id.clone(),
+                ..chunk.clone()
+            })
+            .collect(),
+    )?;
+    if let Err(error) = time_index::index_record(&record_id) {
+        debug!("Could not index imported record: {}", error);
+    }
+
+    Ok(Record {
+        id: record_id,
+        ..record
+    })
+}
+
+/// Whether a platform or game id only holds `[A-Za-z0-9_]` characters, so it
+/// can name a record directory.
+pub(crate) fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+fn extract_files(archive: &Archive, record_path: &Path) -> Result<(), ArchiveError> {
+    let header = &archive.header;
+    let game_data_chunks_path = record_path.join("game_data_chunks");
+    let keyframes_path = record_path.join("keyframes");
+    fs::create_dir_all(&game_data_chunks_path)?;
+    fs::create_dir_all(&keyframes_path)?;
+
+    for (chunk_id, payload) in &header.game_data_chunks {
+        fs::write(
+            game_data_chunks_path.join(chunk_id.to_string()),
+            archive.read_payload(payload)?,
+        )?;
+    }
+    for (keyframe_id, payload) in &header.keyframes {
+        fs::write(
+            keyframes_path.join(keyframe_id.to_string()),
+            archive.read_payload(payload)?,
+        )?;
+    }
+    if let Some(payload) = &header.end_of_game_stats {
+        fs::write(
+            record_path.join("end_of_game_stats"),
+            archive.read_payload(payload)?,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Replace the files of a game of the library by a single archive next to
+/// them, the replay server reads it in place.
+pub fn pack_record(game_id: &str) -> Result<Record, ArchiveError> {
+    let record = queries::get_record(game_id.to_string())?
+        .ok_or_else(|| ArchiveError::RecordNotFound(game_id.to_string()))?;
+    if record.record_status() == Some(RecordStatus::Recording) {
+        return Err(ArchiveError::StillRecording(game_id.to_string()));
+    }
+
+    let record_path = PathBuf::from(&record.storage_path);
+    if record_path.is_file() {
+        return Ok(record);
+    }
+
+    let archive_path = archive_path(&record_path);
+    write_archive(
+        &record,
+        queries::get_record_chunks(&record.id)?,
+        &archive_path,
+    )?;
+
+    let record = Record {
+        storage_path: archive_path.display().to_string(),
+        ..record
+    };
+    queries::save_record(&record)?;
+    fs::remove_dir_all(record_path)?;
+
+    Ok(record)
+}
+
+/// Archive stored in place of the `record_path` directory.
+fn archive_path(record_path: &Path) -> PathBuf {
+    let mut path = record_path.as_os_str().to_owned();
+    path.push(".pyke");
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{stored_record, test_dir};
+
+    #[test]
+    fn test_archive_round_trip() {
+        let dir = test_dir("archive");
+        let record = stored_record(&dir.join("KR_6654667050"));
+        let chunks = vec![RecordChunk {
+            duration: Some(30000),
+            ..RecordChunk::new(record.id.clone(), 1)
+        }];
+        let path = dir.join("game.pyke");
+
+        write_archive(&record, chunks.clone(), &path).unwrap();
+        let archive = Archive::open(&path).unwrap();
+
+        assert_eq!(archive.header.game_id, record.game_id);
+        assert_eq!(archive.header.encryption_key, record.encryption_key);
+        assert_eq!(archive.header.metadata["gameKey"]["gameId"], 6654667050u64);
+        assert_eq!(archive.header.chunks, chunks);
+        assert_eq!(archive.header.end_of_game_stats, None);
+        assert_eq!(
+            archive
+                .read_payload(&archive.header.game_data_chunks[&2])
+                .unwrap(),
+            b"chunk two"
+        );
+        assert_eq!(
+            archive.read_payload(&archive.header.keyframes[&1]).unwrap(),
+            b"keyframe"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_storage_reads_directory_and_archive_alike() {
+        let dir = test_dir("storage");
+        let record = stored_record(&dir.join("KR_6654667050"));
+        let archive_path = archive_path(Path::new(&record.storage_path));
+        write_archive(&record, Vec::new(), &archive_path).unwrap();
+        let packed_record = Record {
+            storage_path: archive_path.display().to_string(),
+            ..stored_record(&dir.join("KR_6654667050"))
+        };
+
+        for record in [record, packed_record] {
+            let storage = storage::RecordStorage::open(&record).unwrap();
+            assert_eq!(storage.game_data_chunk(1).unwrap().unwrap(), b"chunk 1");
+            assert_eq!(storage.keyframe(1).unwrap().unwrap(), b"keyframe");
+            assert_eq!(storage.game_data_chunk(3).unwrap(), None);
+            assert_eq!(storage.end_of_game_stats().unwrap(), None);
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_rejects_paths_in_ids() {
+        let dir = test_dir("import-traversal");
+        let record = Record {
+            game_id: "../../escaped".to_string(),
+            ..stored_record(&dir.join("KR_6654667050"))
+        };
+        let path = dir.join("game.pyke");
+        write_archive(&record, Vec::new(), &path).unwrap();
+
+        assert!(matches!(
+            import_record(&path, &dir.join("storage"), true),
+            Err(ArchiveError::InvalidId("game id"))
+        ));
+        assert!(!dir.join("escaped").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_storage_cache_reuses_archives() {
+        let dir = test_dir("storage-cache");
+        let record = stored_record(&dir.join("KR_6654667050"));
+        let archive_path = archive_path(Path::new(&record.storage_path));
+        write_archive(&record, Vec::new(), &archive_path).unwrap();
+        let packed_record = Record {
+            storage_path: archive_path.display().to_string(),
+            ..record
+        };
+        let cache = storage::StorageCache::new();
+
+        let open = || match cache.open(&packed_record).unwrap() {
+            storage::RecordStorage::Archive(archive) => archive,
+            storage::RecordStorage::Directory(_) => panic!("expected an archive"),
+        };
+        let archive = open();
+        assert!(std::sync::Arc::ptr_eq(&archive, &open()));
+        assert_eq!(
+            archive.read_payload(&archive.header.keyframes[&1]).unwrap(),
+            b"keyframe"
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_rejects_out_of_bounds_lengths() {
+        let dir = test_dir("corrupt");
+        let record = stored_record(&dir.join("KR_6654667050"));
+        let path = dir.join("game.pyke");
+        write_archive(&record, Vec::new(), &path).unwrap();
+        let archive = fs::read(&path).unwrap();
+
+        // Header length past the end of the file
+        let mut corrupt = archive.clone();
+        corrupt[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &corrupt).unwrap();
+        assert!(matches!(Archive::open(&path), Err(ArchiveError::Corrupt)));
+
+        // Payloads past the end of the file
+        fs::write(&path, &archive[..archive.len() - 1]).unwrap();
+        assert!(matches!(Archive::open(&path), Err(ArchiveError::Corrupt)));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_open_rejects_other_files() {
+        let dir = test_dir("not-archive");
+        let path = dir.join("game.rofl");
+        fs::write(&path, b"RIOT\0\0\0\0\0\0\0\0\0\0\0\0").unwrap();
+
+        assert!(matches!(
+            Archive::open(&path),
+            Err(ArchiveError::InvalidFormat)
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
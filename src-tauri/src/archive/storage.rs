@@ -0,0 +1,141 @@
+use super::error::ArchiveError;
+use super::Archive;
+use crate::models::record::Record;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime};
+
+/// Archives kept open by a `StorageCache`, the least recently used one is
+/// dropped beyond that.
+const MAX_CACHED_ARCHIVES: usize = 16;
+
+/// Files of a recorded game, either unpacked in its directory or packed in an
+/// archive. `storage_path` of the record tells which.
+pub enum RecordStorage {
+    Directory(PathBuf),
+    Archive(Arc<Archive>),
+}
+
+impl RecordStorage {
+    pub fn open(record: &Record) -> Result<Self, ArchiveError> {
+        let path = Path::new(&record.storage_path);
+
+        if path.is_file() {
+            Ok(RecordStorage::Archive(Arc::new(Archive::open(path)?)))
+        } else {
+            Ok(RecordStorage::Directory(path.to_path_buf()))
+        }
+    }
+
+    /// Content of a game data chunk, `None` when it was not recorded.
+    pub fn game_data_chunk(&self, chunk_id: u32) -> Result<Option<Vec<u8>>, ArchiveError> {
+        match self {
+            RecordStorage::Directory(path) => {
+                read_file(path.join("game_data_chunks").join(chunk_id.to_string()))
+            }
+            RecordStorage::Archive(archive) => archive
+                .header
+                .game_data_chunks
+                .get(&chunk_id)
+                .map(|payload| archive.read_payload(payload))
+                .transpose(),
+        }
+    }
+
+    /// Content of a keyframe, `None` when it was not recorded.
+    pub fn keyframe(&self, keyframe_id: u32) -> Result<Option<Vec<u8>>, ArchiveError> {
+        match self {
+            RecordStorage::Directory(path) => {
+                read_file(path.join("keyframes").join(keyframe_id.to_string()))
+            }
+            RecordStorage::Archive(archive) => archive
+                .header
+                .keyframes
+                .get(&keyframe_id)
+                .map(|payload| archive.read_payload(payload))
+                .transpose(),
+        }
+    }
+
+    pub fn end_of_game_stats(&self) -> Result<Option<Vec<u8>>, ArchiveError> {
+        match self {
+            RecordStorage::Directory(path) => read_file(path.join("end_of_game_stats")),
+            RecordStorage::Archive(archive) => archive
+                .header
+                .end_of_game_stats
+                .as_ref()
+                .map(|payload| archive.read_payload(payload))
+                .transpose(),
+        }
+    }
+}
+
+struct CachedArchive {
+    archive: Arc<Archive>,
+    modified: SystemTime,
+    used_at: Instant,
+}
+
+/// Archives opened for previous reads, so that serving a packed game does not
+/// parse its header again for every chunk and keyframe.
+#[derive(Default)]
+pub struct StorageCache {
+    archives: Mutex<HashMap<PathBuf, CachedArchive>>,
+}
+
+impl StorageCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like `RecordStorage::open`, reusing the archive opened before as long
+    /// as the file was not modified since.
+    pub fn open(&self, record: &Record) -> Result<RecordStorage, ArchiveError> {
+        let path = Path::new(&record.storage_path);
+        if !path.is_file() {
+            return Ok(RecordStorage::Directory(path.to_path_buf()));
+        }
+
+        let modified = fs::metadata(path)?.modified()?;
+        if let Some(cached) = self.archives.lock().unwrap().get_mut(path) {
+            if cached.modified == modified {
+                cached.used_at = Instant::now();
+                return Ok(RecordStorage::Archive(cached.archive.clone()));
+            }
+        }
+
+        let archive = Arc::new(Archive::open(path)?);
+        let mut archives = self.archives.lock().unwrap();
+        if archives.len() >= MAX_CACHED_ARCHIVES && !archives.contains_key(path) {
+            let least_recently_used = archives
+                .iter()
+                .min_by_key(|(_, cached)| cached.used_at)
+                .map(|(path, _)| path.clone());
+            if let Some(least_recently_used) = least_recently_used {
+                archives.remove(&least_recently_used);
+            }
+        }
+        archives.insert(
+            path.to_path_buf(),
+            CachedArchive {
+                archive: archive.clone(),
+                modified,
+                used_at: Instant::now(),
+            },
+        );
+
+        Ok(RecordStorage::Archive(archive))
+    }
+}
+
+fn read_file(path: PathBuf) -> Result<Option<Vec<u8>>, ArchiveError> {
+    match fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
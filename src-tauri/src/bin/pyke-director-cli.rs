@@ -0,0 +1,440 @@
+use clap::{Parser, Subcommand};
+use pyke_director::decoder::block::Blocks;
+use pyke_director::decoder::time_index;
+use pyke_director::recorder::api::models::{Region, SpectatorEndpoint};
+use pyke_director::recorder::manager::RecorderManager;
+use pyke_director::recorder::models::RecordingHandle;
+use pyke_director::recorder::process;
+use pyke_director::server::live::LiveSessions;
+use pyke_director::server::spectator::{self, ServerAddress};
+use pyke_director::verify::{self, MediaReport};
+use pyke_director::{archive, db, decoder, queries, rofl, settings};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+/// Record and replay League of Legends games without the desktop app. Uses
+/// the same settings, database and storage layout.
+#[derive(Parser)]
+#[command(name = "pyke-director-cli", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Record a game from a Riot spectator region
+    Record {
+        /// Region of the game, the default region from the settings otherwise
+        #[arg(long)]
+        region: Option<Region>,
+        #[arg(long)]
+        game_id: String,
+        /// Observer encryption key of the game
+        #[arg(long)]
+        key: String,
+        /// Storage directory, the one from the settings otherwise
+        #[arg(long)]
+        storage_path: Option<PathBuf>,
+    },
+    /// Record a game from any spectator endpoint
+    RecordCustom {
+        #[arg(long)]
+        base_url: String,
+        #[arg(long)]
+        platform_id: String,
+        #[arg(long)]
+        game_id: String,
+        /// Observer encryption key of the game
+        #[arg(long)]
+        key: String,
+        /// Storage directory, the one from the settings otherwise
+        #[arg(long)]
+        storage_path: Option<PathBuf>,
+    },
+    /// Run the replay server
+    Serve,
+    /// Run the replay server with the control API under `/api`, resuming the
+    /// recordings interrupted by a previous run
+    Daemon,
+    /// List the recorded games
+    List,
+    /// Show a recorded game
+    Show { game_id: String },
+    /// Pack a recorded game into a single archive file
+    Export { game_id: String, path: PathBuf },
+    /// Add a game from an archive file to the library
+    Import {
+        path: PathBuf,
+        /// Storage directory, the one from the settings otherwise
+        #[arg(long)]
+        storage_path: Option<PathBuf>,
+        /// Keep the game packed in the archive instead of unpacking its files
+        #[arg(long)]
+        packed: bool,
+    },
+    /// Replace the files of a recorded game by a single archive
+    Pack { game_id: String },
+    /// Write a recorded game as a .rofl replay
+    ExportRofl { game_id: String, path: PathBuf },
+    /// Add a .rofl replay to the library
+    ImportRofl {
+        path: PathBuf,
+        /// Platform of the game, guessed from the file name otherwise
+        #[arg(long)]
+        platform_id: Option<String>,
+        /// Storage directory, the one from the settings otherwise
+        #[arg(long)]
+        storage_path: Option<PathBuf>,
+    },
+    /// Dump the decrypted and decompressed content of a game data chunk
+    Decode {
+        game_id: String,
+        chunk_id: u32,
+        /// Decode the keyframe `chunk_id` instead
+        #[arg(long)]
+        key_frame: bool,
+        /// List the blocks of the decoded data instead of dumping it
+        #[arg(long)]
+        blocks: bool,
+        /// File to write, the standard output otherwise
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Index the game time covered by each chunk and keyframe of a recorded
+    /// game and report the holes between chunks
+    Index { game_id: String },
+    /// Check that a recorded game holds every chunk and keyframe and that
+    /// they decode
+    Verify {
+        platform_id: String,
+        game_id: String,
+        /// Download again the missing or broken files while the game is
+        /// still available
+        #[arg(long)]
+        repair: bool,
+    },
+}
+
+fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let cli = Cli::parse();
+
+    if let Err(error) = db::init() {
+        eprintln!("error: {}", error);
+        return ExitCode::FAILURE;
+    }
+
+    let result = match cli.command {
+        Command::Record {
+            region,
+            game_id,
+            key,
+            storage_path,
+        } => {
+            let region = region.unwrap_or(settings::current().default_region);
+            record(region.to_endpoint(), game_id, key, storage_path)
+        }
+        Command::RecordCustom {
+            base_url,
+            platform_id,
+            game_id,
+            key,
+            storage_path,
+        } => record(
+            SpectatorEndpoint::new(base_url, platform_id),
+            game_id,
+            key,
+            storage_path,
+        ),
+        Command::Serve => serve(),
+        Command::Daemon => daemon(),
+        Command::List => list(),
+        Command::Show { game_id } => show(game_id),
+        Command::Export { game_id, path } => {
+            archive::export_record(&game_id, &path).map_err(|error| error.to_string())
+        }
+        Command::Import {
+            path,
+            storage_path,
+            packed,
+        } => {
+            let storage_path = storage_path.unwrap_or(settings::current().storage_path);
+            archive::import_record(&path, &storage_path, !packed)
+                .map(|record| {
+                    println!(
+                        "Imported game {} in {}",
+                        record.game_id, record.storage_path
+                    )
+                })
+                .map_err(|error| error.to_string())
+        }
+        Command::Pack { game_id } => archive::pack_record(&game_id)
+            .map(|record| println!("Packed game {} in {}", record.game_id, record.storage_path))
+            .map_err(|error| error.to_string()),
+        Command::ExportRofl { game_id, path } => {
+            rofl::export_record(&game_id, &path).map_err(|error| error.to_string())
+        }
+        Command::ImportRofl {
+            path,
+            platform_id,
+            storage_path,
+        } => {
+            let storage_path = storage_path.unwrap_or(settings::current().storage_path);
+            rofl::import_record(&path, &storage_path, platform_id)
+                .map(|record| {
+                    println!(
+                        "Imported game {} in {}",
+                        record.game_id, record.storage_path
+                    )
+                })
+                .map_err(|error| error.to_string())
+        }
+        Command::Decode {
+            game_id,
+            chunk_id,
+            key_frame,
+            blocks,
+            output,
+        } => decode(game_id, chunk_id, key_frame, blocks, output),
+        Command::Index { game_id } => index(game_id),
+        Command::Verify {
+            platform_id,
+            game_id,
+            repair,
+        } => verify(platform_id, game_id, repair),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Record until the game ends, Ctrl-C stops early and keeps what was stored.
+fn record(
+    endpoint: SpectatorEndpoint,
+    game_id: String,
+    encryption_key: String,
+    storage_path: Option<PathBuf>,
+) -> Result<(), String> {
+    let storage_path = storage_path.unwrap_or(settings::current().storage_path);
+    let runtime = tokio::runtime::Runtime::new().map_err(|error| error.to_string())?;
+
+    runtime.block_on(async move {
+        let (events, mut receiver) = broadcast::channel(256);
+        let handle = RecordingHandle::new(endpoint.platform_id.clone(), game_id.clone(), events);
+
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => println!("{}", serde_json::to_string(&event).unwrap()),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let cancel_handle = handle.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                eprintln!("Stopping the recording...");
+                cancel_handle.cancel();
+            }
+        });
+
+        process::new(endpoint, game_id, encryption_key, storage_path, handle)
+            .await
+            .map(|record| println!("Recorded in {}", record.storage_path.display()))
+            .map_err(|error| error.to_string())
+    })
+}
+
+fn serve() -> Result<(), String> {
+    // Actix runs its own runtime, the server blocks until stopped
+    spectator::init(
+        Arc::new(LiveSessions::new()),
+        Arc::new(ServerAddress::new()),
+        None,
+    )
+    .map_err(|error| error.to_string())
+}
+
+fn daemon() -> Result<(), String> {
+    // Recordings run on their own runtime so they are not tied to the server
+    // workers, the API only starts them
+    let runtime = tokio::runtime::Runtime::new().map_err(|error| error.to_string())?;
+    let manager = Arc::new(RecorderManager::with_runtime(runtime.handle().clone()));
+
+    let mut events = manager.subscribe();
+    runtime.spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => println!("{}", serde_json::to_string(&event).unwrap()),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    let resumed_manager = manager.clone();
+    runtime.spawn(async move { resumed_manager.resume_interrupted().await });
+
+    spectator::init(
+        Arc::new(LiveSessions::new()),
+        Arc::new(ServerAddress::new()),
+        Some(manager),
+    )
+    .map_err(|error| error.to_string())
+}
+
+fn list() -> Result<(), String> {
+    for record in queries::get_records().map_err(|error| error.to_string())? {
+        println!(
+            "{}\t{}\t{}\t{}\tchunks 1-{}\t{}",
+            record.platform_id,
+            record.game_id,
+            record.status,
+            record.created_at,
+            record.last_contiguous_game_data_chunk(),
+            record.storage_path
+        );
+    }
+
+    Ok(())
+}
+
+fn show(game_id: String) -> Result<(), String> {
+    let record = queries::get_record(game_id.clone())
+        .map_err(|error| error.to_string())?
+        .ok_or_else(|| format!("no record for game {}", game_id))?;
+
+    println!("{}", serde_json::to_string_pretty(&record).unwrap());
+    if let Some(metadata) = record.game_meta_data() {
+        println!("{}", metadata);
+    }
+
+    Ok(())
+}
+
+fn decode(
+    game_id: String,
+    chunk_id: u32,
+    key_frame: bool,
+    blocks: bool,
+    output: Option<PathBuf>,
+) -> Result<(), String> {
+    let record = queries::get_record(game_id.clone())
+        .map_err(|error| error.to_string())?
+        .ok_or_else(|| format!("no record for game {}", game_id))?;
+    let data = if key_frame {
+        decoder::decode_key_frame(&record.id, chunk_id)
+    } else {
+        decoder::decode_chunk(&record.id, chunk_id)
+    }
+    .map_err(|error| error.to_string())?;
+
+    let content = if blocks {
+        list_blocks(&data)?.into_bytes()
+    } else {
+        data
+    };
+
+    match output {
+        Some(path) => fs::write(path, content),
+        None => io::stdout().write_all(&content),
+    }
+    .map_err(|error| error.to_string())
+}
+
+/// One line per block: time, packet type, parameter and data length.
+fn list_blocks(data: &[u8]) -> Result<String, String> {
+    let mut listing = String::new();
+
+    for block in Blocks::new(data) {
+        let block = block.map_err(|error| error.to_string())?;
+        listing.push_str(&format!(
+            "{:.3}\t{:#06x}\t{:#010x}\t{}\n",
+            block.time,
+            block.packet_type,
+            block.param,
+            block.data.len()
+        ));
+    }
+
+    Ok(listing)
+}
+
+fn index(game_id: String) -> Result<(), String> {
+    let record = queries::get_record(game_id.clone())
+        .map_err(|error| error.to_string())?
+        .ok_or_else(|| format!("no record for game {}", game_id))?;
+    let index = time_index::index_record(&record.id).map_err(|error| error.to_string())?;
+
+    for range in index.chunks.iter().chain(&index.keyframes) {
+        println!(
+            "{}\t{}\t{:.3}\t{:.3}",
+            range.kind, range.media_id, range.start_time, range.end_time
+        );
+    }
+    for hole in index.holes() {
+        println!(
+            "hole between chunks {} and {}: {:.3} to {:.3}",
+            hole.after_chunk_id, hole.before_chunk_id, hole.start_time, hole.end_time
+        );
+    }
+
+    Ok(())
+}
+
+fn verify(platform_id: String, game_id: String, repair: bool) -> Result<(), String> {
+    let report = if repair {
+        let runtime = tokio::runtime::Runtime::new().map_err(|error| error.to_string())?;
+        let repaired = runtime
+            .block_on(verify::repair_record(&platform_id, &game_id))
+            .map_err(|error| error.to_string())?;
+        if !repaired.available {
+            println!("game {} is no longer available", game_id);
+        }
+        println!(
+            "refetched chunks {:?} keyframes {:?}",
+            repaired.refetched_game_data_chunks, repaired.refetched_keyframes
+        );
+        repaired.report
+    } else {
+        verify::verify_record(&platform_id, &game_id).map_err(|error| error.to_string())?
+    };
+
+    print_media_report("chunks", &report.game_data_chunks);
+    print_media_report("keyframes", &report.keyframes);
+
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(format!("game {} is incomplete", game_id))
+    }
+}
+
+fn print_media_report(name: &str, report: &MediaReport) {
+    println!("{}: 1-{}", name, report.last_id);
+    for (problem, ids) in [
+        ("missing", &report.missing),
+        ("empty", &report.empty),
+        ("undecodable", &report.undecodable),
+        ("not in database", &report.not_in_database),
+        ("not on disk", &report.not_on_disk),
+    ] {
+        if !ids.is_empty() {
+            println!("  {}: {:?}", problem, ids);
+        }
+    }
+}
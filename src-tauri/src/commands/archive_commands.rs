@@ -0,0 +1,33 @@
+use pyke_director::archive;
+use pyke_director::models::record::Record;
+use pyke_director::settings;
+use tauri::async_runtime::spawn_blocking;
+
+use std::path::PathBuf;
+
+#[tauri::command]
+pub async fn export_record(game_id: String, path: PathBuf) -> Result<(), String> {
+    spawn_blocking(move || archive::export_record(&game_id, &path))
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())
+}
+
+/// Add an archive to the library, unpacked unless `extract` is false.
+#[tauri::command]
+pub async fn import_record(path: PathBuf, extract: Option<bool>) -> Result<Record, String> {
+    let storage_path = settings::current().storage_path;
+
+    spawn_blocking(move || archive::import_record(&path, &storage_path, extract.unwrap_or(true)))
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())
+}
+
+#[tauri::command]
+pub async fn pack_record(game_id: String) -> Result<Record, String> {
+    spawn_blocking(move || archive::pack_record(&game_id))
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())
+}
@@ -0,0 +1,42 @@
+use pyke_director::decoder;
+use pyke_director::decoder::time_index::{self, TimeIndex};
+use pyke_director::queries;
+use tauri::async_runtime::spawn_blocking;
+
+use std::fs;
+use std::path::PathBuf;
+
+/// Write the decrypted and decompressed content of a game data chunk, or of a
+/// keyframe when `key_frame` is set, to `path`.
+#[tauri::command]
+pub async fn dump_decoded_chunk(
+    game_id: String,
+    chunk_id: u32,
+    key_frame: Option<bool>,
+    path: PathBuf,
+) -> Result<(), String> {
+    spawn_blocking(move || {
+        let record = queries::get_record(game_id.clone())
+            .map_err(|error| error.to_string())?
+            .ok_or_else(|| format!("no record for game {}", game_id))?;
+        let data = if key_frame.unwrap_or(false) {
+            decoder::decode_key_frame(&record.id, chunk_id)
+        } else {
+            decoder::decode_chunk(&record.id, chunk_id)
+        }
+        .map_err(|error| error.to_string())?;
+
+        fs::write(path, data).map_err(|error| error.to_string())
+    })
+    .await
+    .map_err(|error| error.to_string())?
+}
+
+/// Index the game time covered by each chunk and keyframe of a record.
+#[tauri::command]
+pub async fn index_record(record_id: String) -> Result<TimeIndex, String> {
+    spawn_blocking(move || time_index::index_record(&record_id))
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())
+}
@@ -1 +1,7 @@
+pub mod archive_commands;
+pub mod decoder_commands;
 pub mod record_commands;
+pub mod replay_commands;
+pub mod rofl_commands;
+pub mod settings_commands;
+pub mod verify_commands;
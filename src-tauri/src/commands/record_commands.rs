@@ -1,66 +1,103 @@
-use crate::models::record::Record;
-use crate::queries;
-use crate::recorder;
-use crate::recorder::api::models::{Region, SpectatorEndpoint};
-use uuid::Uuid;
+use pyke_director::recorder::api::models::{Region, SpectatorEndpoint};
+use pyke_director::recorder::featured::AutoRecordFilter;
+use pyke_director::recorder::manager::RecorderManager;
+use pyke_director::recorder::models::RecordingStatus;
+use pyke_director::settings;
 
-use std::path::Path;
+use tauri::State;
+
+use std::sync::Arc;
 
 #[tauri::command]
-pub async fn record(region: Region, game_id: String, encryption_key: String) -> Result<String, ()> {
+pub async fn record(
+    manager: State<'_, Arc<RecorderManager>>,
+    region: Option<Region>,
+    game_id: String,
+    encryption_key: String,
+) -> Result<RecordingStatus, String> {
+    let settings = settings::current();
+    let region = region.unwrap_or(settings.default_region);
     println!(
         "record: region {} game_id {} encryption_key {}",
         region, game_id, encryption_key
     );
     let endpoint = region.to_endpoint();
-    let storage_path = Path::new("/home/john/Downloads/lol").to_path_buf();
+    let storage_path = settings.storage_path;
 
-    recorder::process::new(endpoint, game_id, encryption_key, storage_path).await;
-
-    Ok("Ok".to_string())
+    manager
+        .start(endpoint, game_id, encryption_key, storage_path)
+        .map_err(|error| error.to_string())
 }
 
 #[tauri::command]
 pub async fn record_custom_endpoint(
+    manager: State<'_, Arc<RecorderManager>>,
     base_url: String,
     platform_id: String,
     game_id: String,
     encryption_key: String,
-) -> Result<String, ()> {
+) -> Result<RecordingStatus, String> {
     println!("record custom endpoint");
 
     let endpoint = SpectatorEndpoint::new(base_url, platform_id);
-    let storage_path = Path::new("/home/john/Downloads/lol").to_path_buf();
-
-    match recorder::process::new(endpoint, game_id, encryption_key, storage_path).await {
-        Err(error) => {
-            println!("error {}", error)
-        }
-        Ok(record) => {
-            let locked_keyframes = record.keyframes.lock().unwrap();
-            let serialized_keyframes = serde_json::to_string(&*locked_keyframes).unwrap();
-
-            let locked_game_data_chunks = record.game_data_chunks.lock().unwrap();
-            let serialized_game_data_chunks =
-                serde_json::to_string(&*locked_game_data_chunks).unwrap();
-
-            let new_record = Record {
-                id: Uuid::new_v4().to_string(),
-                base_url: record.endpoint.base_url,
-                platform_id: record.endpoint.platform_id,
-                version: record.version,
-                game_id: record.game_id,
-                encryption_key: record.encryption_key,
-                metadata: serde_json::to_string(&record.metadata).unwrap(),
-                storage_path: record.storage_path.display().to_string(),
-                keyframes: serialized_keyframes,
-                game_data_chunks: serialized_game_data_chunks,
-                created_at: chrono::Utc::now().naive_utc(),
-            };
-            queries::create_record(&new_record);
-            println!("success record done");
-        }
-    }
-
-    Ok("Ok".to_string())
+    let storage_path = settings::current().storage_path;
+
+    manager
+        .start(endpoint, game_id, encryption_key, storage_path)
+        .map_err(|error| error.to_string())
+}
+
+#[tauri::command]
+pub fn list_active_recordings(manager: State<'_, Arc<RecorderManager>>) -> Vec<RecordingStatus> {
+    manager.list()
+}
+
+#[tauri::command]
+pub fn get_recording_status(
+    manager: State<'_, Arc<RecorderManager>>,
+    platform_id: String,
+    game_id: String,
+) -> Option<RecordingStatus> {
+    manager.status(&platform_id, &game_id)
+}
+
+#[tauri::command]
+pub fn cancel_recording(
+    manager: State<'_, Arc<RecorderManager>>,
+    platform_id: String,
+    game_id: String,
+) -> bool {
+    manager.cancel(&platform_id, &game_id)
+}
+
+#[tauri::command]
+pub async fn start_auto_record(
+    manager: State<'_, Arc<RecorderManager>>,
+    region: Region,
+    filter: Option<AutoRecordFilter>,
+) -> Result<(), String> {
+    let storage_path = settings::current().storage_path;
+
+    manager.start_auto_record(
+        region.to_endpoint(),
+        filter.unwrap_or_default(),
+        storage_path,
+    );
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_auto_record(manager: State<'_, Arc<RecorderManager>>, region: Region) -> bool {
+    manager.stop_auto_record(&region.to_endpoint().platform_id)
+}
+
+#[tauri::command]
+pub fn list_auto_records(manager: State<'_, Arc<RecorderManager>>) -> Vec<String> {
+    manager.list_auto_records()
+}
+
+#[tauri::command]
+pub fn list_regions() -> Vec<SpectatorEndpoint> {
+    Region::ALL.iter().map(Region::to_endpoint).collect()
 }
@@ -0,0 +1,32 @@
+use pyke_director::server::live::LiveSessions;
+use pyke_director::server::spectator::ServerAddress;
+
+use tauri::State;
+
+use std::sync::Arc;
+
+#[tauri::command]
+pub fn start_live_replay(
+    live_sessions: State<'_, Arc<LiveSessions>>,
+    platform_id: String,
+    game_id: String,
+    start_chunk_id: Option<u32>,
+) {
+    live_sessions.start(platform_id, game_id, start_chunk_id);
+}
+
+#[tauri::command]
+pub fn stop_live_replay(
+    live_sessions: State<'_, Arc<LiveSessions>>,
+    platform_id: String,
+    game_id: String,
+) -> bool {
+    live_sessions.stop(&platform_id, &game_id)
+}
+
+/// Address to put in the client spectator launch string, if the replay server
+/// is running.
+#[tauri::command]
+pub fn get_replay_server_address(server_address: State<'_, Arc<ServerAddress>>) -> Option<String> {
+    server_address.get().map(|address| address.to_string())
+}
@@ -0,0 +1,24 @@
+use pyke_director::models::record::Record;
+use pyke_director::rofl;
+use pyke_director::settings;
+use tauri::async_runtime::spawn_blocking;
+
+use std::path::PathBuf;
+
+#[tauri::command]
+pub async fn export_rofl(game_id: String, path: PathBuf) -> Result<(), String> {
+    spawn_blocking(move || rofl::export_record(&game_id, &path))
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())
+}
+
+#[tauri::command]
+pub async fn import_rofl(path: PathBuf, platform_id: Option<String>) -> Result<Record, String> {
+    let storage_path = settings::current().storage_path;
+
+    spawn_blocking(move || rofl::import_record(&path, &storage_path, platform_id))
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())
+}
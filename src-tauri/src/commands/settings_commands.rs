@@ -0,0 +1,20 @@
+use pyke_director::db;
+use pyke_director::settings::{self, Settings};
+
+#[tauri::command]
+pub fn get_settings() -> Settings {
+    settings::current()
+}
+
+#[tauri::command]
+pub fn update_settings(new_settings: Settings) -> Result<Settings, String> {
+    let database_changed = new_settings.database_path != settings::current().database_path;
+    let settings = settings::update(new_settings).map_err(|error| error.to_string())?;
+
+    // Create and migrate the database at its new location
+    if database_changed {
+        db::init().map_err(|error| error.to_string())?;
+    }
+
+    Ok(settings)
+}
@@ -0,0 +1,20 @@
+use pyke_director::verify::{self, RepairReport, VerifyReport};
+use tauri::async_runtime::spawn_blocking;
+
+#[tauri::command]
+pub async fn verify_record(platform_id: String, game_id: String) -> Result<VerifyReport, String> {
+    // Every stored file is read and decoded
+    spawn_blocking(move || verify::verify_record(&platform_id, &game_id))
+        .await
+        .map_err(|error| error.to_string())?
+        .map_err(|error| error.to_string())
+}
+
+/// Download again the missing or broken files of a record while the game is
+/// still available.
+#[tauri::command]
+pub async fn repair_record(platform_id: String, game_id: String) -> Result<RepairReport, String> {
+    verify::repair_record(&platform_id, &game_id)
+        .await
+        .map_err(|error| error.to_string())
+}
@@ -1,48 +1,159 @@
+use std::error::Error;
 use std::fs;
+use std::io;
+use std::ops::{Deref, DerefMut};
 use std::path::Path;
+use std::sync::{Mutex, OnceLock};
 
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use thiserror::Error;
+use tokio::task::{spawn_blocking, JoinError};
+
+use crate::settings;
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
-pub fn init() {
+/// Idle connections kept open for reuse, more are closed once released.
+const MAX_IDLE_CONNECTIONS: usize = 8;
+
+/// How long a query waits for another connection to release its lock.
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+#[derive(Error, Debug)]
+pub enum DbError {
+    #[error("could not connect to the database: {0}")]
+    Connection(#[from] ConnectionError),
+
+    #[error("database error: {0}")]
+    Query(#[from] diesel::result::Error),
+
+    #[error("database task failed: {0}")]
+    Blocking(#[from] JoinError),
+
+    #[error("could not create the database file: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("could not migrate the database: {0}")]
+    Migration(Box<dyn Error + Send + Sync>),
+}
+
+/// Create the database file when missing and bring its schema up to date.
+pub fn init() -> Result<(), DbError> {
     if !db_file_exists() {
-        create_db_file();
+        create_db_file()?;
+    }
+
+    run_migrations()
+}
+
+/// Connections of the database in use, dropped when the settings point to
+/// another one.
+#[derive(Default)]
+struct ConnectionPool {
+    db_path: String,
+    idle: Vec<SqliteConnection>,
+}
+
+fn pool() -> &'static Mutex<ConnectionPool> {
+    static POOL: OnceLock<Mutex<ConnectionPool>> = OnceLock::new();
+
+    POOL.get_or_init(Default::default)
+}
+
+/// Connection taken from the pool, given back when dropped.
+pub struct PooledConnection {
+    db_path: String,
+    connection: Option<SqliteConnection>,
+}
+
+impl Deref for PooledConnection {
+    type Target = SqliteConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.connection.as_mut().unwrap()
     }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let mut pool = pool().lock().unwrap();
+
+        if pool.db_path == self.db_path && pool.idle.len() < MAX_IDLE_CONNECTIONS {
+            if let Some(connection) = self.connection.take() {
+                pool.idle.push(connection);
+            }
+        }
+    }
+}
+
+/// Connection to the database, reusing an idle one when there is one. Queries
+/// block, async code runs them through `blocking`.
+pub fn connection() -> Result<PooledConnection, DbError> {
+    let db_path = get_db_path();
+    let idle = {
+        let mut pool = pool().lock().unwrap();
+        if pool.db_path != db_path {
+            pool.idle.clear();
+            pool.db_path = db_path.clone();
+        }
+        pool.idle.pop()
+    };
+
+    let connection = match idle {
+        Some(connection) => connection,
+        None => open_connection(&db_path)?,
+    };
 
-    run_migrations();
+    Ok(PooledConnection {
+        db_path,
+        connection: Some(connection),
+    })
 }
 
-pub fn establish_db_connection() -> SqliteConnection {
-    let db_path = get_db_path().clone();
+fn open_connection(db_path: &str) -> Result<SqliteConnection, DbError> {
+    let mut connection = SqliteConnection::establish(db_path)?;
+    // Recordings write concurrently, wait for the lock instead of failing
+    diesel::sql_query(format!("PRAGMA busy_timeout = {}", BUSY_TIMEOUT_MS))
+        .execute(&mut connection)?;
 
-    SqliteConnection::establish(db_path.as_str())
-        .unwrap_or_else(|_| panic!("Error connecting to {}", db_path))
+    Ok(connection)
 }
 
-fn run_migrations() {
-    let mut connection = establish_connection();
-    connection.run_pending_migrations(MIGRATIONS).unwrap();
+/// Run database work on the blocking thread pool, off the async workers.
+pub async fn blocking<F, T>(f: F) -> Result<T, DbError>
+where
+    F: FnOnce() -> Result<T, DbError> + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_blocking(f).await?
 }
 
-fn establish_connection() -> SqliteConnection {
-    let db_path = "sqlite://".to_string() + get_db_path().as_str();
+fn run_migrations() -> Result<(), DbError> {
+    let mut connection = connection()?;
+    connection
+        .run_pending_migrations(MIGRATIONS)
+        .map_err(DbError::Migration)?;
 
-    SqliteConnection::establish(&db_path)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", db_path))
+    Ok(())
 }
 
-fn create_db_file() {
+fn create_db_file() -> Result<(), DbError> {
     let db_path = get_db_path();
-    let db_dir = Path::new(&db_path).parent().unwrap();
 
-    if !db_dir.exists() {
-        fs::create_dir_all(db_dir).unwrap();
+    if let Some(db_dir) = Path::new(&db_path).parent() {
+        fs::create_dir_all(db_dir)?;
     }
+    fs::File::create(db_path)?;
 
-    fs::File::create(db_path).unwrap();
+    Ok(())
 }
 
 fn db_file_exists() -> bool {
@@ -51,6 +162,5 @@ fn db_file_exists() -> bool {
 }
 
 fn get_db_path() -> String {
-    let home_dir = dirs::home_dir().unwrap();
-    home_dir.to_str().unwrap().to_string() + "/.config/pyke-director/database.sqlite"
+    settings::current().database_path.display().to_string()
 }
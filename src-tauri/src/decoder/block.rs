@@ -0,0 +1,161 @@
+use super::error::BlockError;
+
+/// The time is an u8 of milliseconds since the previous block instead of an
+/// f32 of seconds.
+const RELATIVE_TIME: u8 = 0x80;
+/// The packet type is the one of the previous block instead of an u16.
+const SAME_TYPE: u8 = 0x40;
+/// The parameter is an i8 offset from the previous block instead of an u32.
+const RELATIVE_PARAM: u8 = 0x20;
+/// The length is an u8 instead of an u32.
+const SHORT_LENGTH: u8 = 0x10;
+
+/// A packet of a decoded game data chunk or keyframe.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block<'a> {
+    /// Game time in seconds.
+    pub time: f32,
+    pub packet_type: u16,
+    /// Usually the net id of the game object the packet is about.
+    pub param: u32,
+    pub data: &'a [u8],
+}
+
+/// Iterates over the blocks of decoded data. Each block starts with a marker
+/// byte telling how its header fields are encoded, most of them are relative
+/// to the previous block. Iteration stops after the first error.
+pub struct Blocks<'a> {
+    data: &'a [u8],
+    position: usize,
+    time: f32,
+    packet_type: u16,
+    param: u32,
+    failed: bool,
+}
+
+impl<'a> Blocks<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Blocks {
+            data,
+            position: 0,
+            time: 0.0,
+            packet_type: 0,
+            param: 0,
+            failed: false,
+        }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], BlockError> {
+        let bytes = self
+            .position
+            .checked_add(length)
+            .and_then(|end| self.data.get(self.position..end))
+            .ok_or(BlockError::Truncated(self.position))?;
+        self.position += length;
+
+        Ok(bytes)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], BlockError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn read_block(&mut self) -> Result<Block<'a>, BlockError> {
+        let [marker] = self.take_array()?;
+
+        self.time = if marker & RELATIVE_TIME != 0 {
+            let [milliseconds] = self.take_array()?;
+            self.time + milliseconds as f32 / 1000.0
+        } else {
+            f32::from_le_bytes(self.take_array()?)
+        };
+        let length = if marker & SHORT_LENGTH != 0 {
+            u8::from_le_bytes(self.take_array()?) as usize
+        } else {
+            u32::from_le_bytes(self.take_array()?) as usize
+        };
+        if marker & SAME_TYPE == 0 {
+            self.packet_type = u16::from_le_bytes(self.take_array()?);
+        }
+        self.param = if marker & RELATIVE_PARAM != 0 {
+            let offset = i8::from_le_bytes(self.take_array()?);
+            self.param.wrapping_add_signed(offset as i32)
+        } else {
+            u32::from_le_bytes(self.take_array()?)
+        };
+
+        Ok(Block {
+            time: self.time,
+            packet_type: self.packet_type,
+            param: self.param,
+            data: self.take(length)?,
+        })
+    }
+}
+
+impl<'a> Iterator for Blocks<'a> {
+    type Item = Result<Block<'a>, BlockError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.position >= self.data.len() {
+            return None;
+        }
+
+        let block = self.read_block();
+        self.failed = block.is_err();
+
+        Some(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_absolute_then_relative_blocks() {
+        let mut data = vec![0x00];
+        data.extend(12.5f32.to_le_bytes());
+        data.extend(3u32.to_le_bytes());
+        data.extend(0x0123u16.to_le_bytes());
+        data.extend(0x4000_0010u32.to_le_bytes());
+        data.extend([1, 2, 3]);
+        data.extend([RELATIVE_TIME | SAME_TYPE | RELATIVE_PARAM | SHORT_LENGTH]);
+        data.extend([250, 1, (-2i8) as u8, 9]);
+
+        let blocks: Vec<Block> = Blocks::new(&data).collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(
+            blocks,
+            vec![
+                Block {
+                    time: 12.5,
+                    packet_type: 0x0123,
+                    param: 0x4000_0010,
+                    data: &[1, 2, 3],
+                },
+                Block {
+                    time: 12.75,
+                    packet_type: 0x0123,
+                    param: 0x4000_000e,
+                    data: &[9],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_truncated_block() {
+        let data = [
+            SHORT_LENGTH | SAME_TYPE | RELATIVE_PARAM | RELATIVE_TIME,
+            10,
+            4,
+            1,
+            0xff,
+        ];
+        let mut blocks = Blocks::new(&data);
+
+        assert_eq!(blocks.next(), Some(Err(BlockError::Truncated(4))));
+        assert_eq!(blocks.next(), None);
+    }
+}
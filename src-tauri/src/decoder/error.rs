@@ -0,0 +1,43 @@
+use crate::archive::error::ArchiveError;
+use crate::db::DbError;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum DecodeError {
+    #[error("encryption key is not valid base64: {0}")]
+    Base64(#[from] base64::DecodeError),
+
+    #[error("invalid Blowfish key length")]
+    InvalidKeyLength,
+
+    #[error("encrypted data length {0} is not a multiple of the block size")]
+    InvalidLength(usize),
+
+    #[error("invalid padding after decryption")]
+    InvalidPadding,
+
+    #[error("invalid gzip data: {0}")]
+    Gzip(std::io::Error),
+
+    #[error("invalid decoded data: {0}")]
+    Block(#[from] BlockError),
+
+    #[error("could not read the recorded files: {0}")]
+    Storage(#[from] ArchiveError),
+
+    #[error("could not access the database: {0}")]
+    Database(#[from] DbError),
+
+    #[error("no record with id {0}")]
+    RecordNotFound(String),
+
+    #[error("{0} was not recorded")]
+    NotRecorded(String),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum BlockError {
+    #[error("block at byte {0} is truncated")]
+    Truncated(usize),
+}
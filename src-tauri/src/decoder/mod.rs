@@ -0,0 +1,149 @@
+pub mod block;
+pub mod error;
+pub mod time_index;
+
+use crate::archive::error::ArchiveError;
+use crate::archive::storage::RecordStorage;
+use crate::queries;
+use error::DecodeError;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blowfish::cipher::generic_array::GenericArray;
+use blowfish::cipher::{BlockDecrypt, KeyInit};
+use blowfish::Blowfish;
+use flate2::read::GzDecoder;
+
+use std::io::Read;
+
+const BLOCK_SIZE: usize = 8;
+
+/// Decrypts the game data chunks and keyframes of a game.
+///
+/// The observer encryption key is base64 and itself encrypted, with the game
+/// id as key. Once decrypted it is the key of every chunk and keyframe, which
+/// are Blowfish ECB encrypted with PKCS#5 padding, then gzipped.
+pub struct ChunkDecoder {
+    cipher: Blowfish,
+}
+
+impl ChunkDecoder {
+    pub fn new(encryption_key: &str, game_id: &str) -> Result<Self, DecodeError> {
+        let chunk_key = decrypt(
+            &cipher(game_id.as_bytes())?,
+            &BASE64.decode(encryption_key)?,
+        )?;
+
+        Ok(ChunkDecoder {
+            cipher: cipher(&chunk_key)?,
+        })
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let compressed = decrypt(&self.cipher, data)?;
+        let mut decoded = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decoded)
+            .map_err(DecodeError::Gzip)?;
+
+        Ok(decoded)
+    }
+}
+
+fn cipher(key: &[u8]) -> Result<Blowfish, DecodeError> {
+    Blowfish::new_from_slice(key).map_err(|_| DecodeError::InvalidKeyLength)
+}
+
+/// Blowfish ECB decryption, without the PKCS#5 padding.
+fn decrypt(cipher: &Blowfish, data: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    if data.is_empty() || !data.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(DecodeError::InvalidLength(data.len()));
+    }
+
+    let mut decrypted = data.to_vec();
+    for block in decrypted.chunks_exact_mut(BLOCK_SIZE) {
+        cipher.decrypt_block(GenericArray::from_mut_slice(block));
+    }
+
+    let padding = *decrypted.last().unwrap() as usize;
+    if padding == 0
+        || padding > BLOCK_SIZE
+        || !decrypted[decrypted.len() - padding..]
+            .iter()
+            .all(|&byte| byte as usize == padding)
+    {
+        return Err(DecodeError::InvalidPadding);
+    }
+    decrypted.truncate(decrypted.len() - padding);
+
+    Ok(decrypted)
+}
+
+/// Decrypted and decompressed content of a stored game data chunk.
+pub fn decode_chunk(record_id: &str, chunk_id: u32) -> Result<Vec<u8>, DecodeError> {
+    decode_stored(record_id, |storage| storage.game_data_chunk(chunk_id)).and_then(|data| {
+        data.ok_or_else(|| DecodeError::NotRecorded(format!("chunk {}", chunk_id)))
+    })
+}
+
+/// Decrypted and decompressed content of a stored keyframe.
+pub fn decode_key_frame(record_id: &str, keyframe_id: u32) -> Result<Vec<u8>, DecodeError> {
+    decode_stored(record_id, |storage| storage.keyframe(keyframe_id)).and_then(|data| {
+        data.ok_or_else(|| DecodeError::NotRecorded(format!("keyframe {}", keyframe_id)))
+    })
+}
+
+fn decode_stored<F>(record_id: &str, read: F) -> Result<Option<Vec<u8>>, DecodeError>
+where
+    F: FnOnce(&RecordStorage) -> Result<Option<Vec<u8>>, ArchiveError>,
+{
+    let record = queries::get_record_by_id(record_id)?
+        .ok_or_else(|| DecodeError::RecordNotFound(record_id.to_string()))?;
+    let decoder = ChunkDecoder::new(&record.encryption_key, &record.game_id)?;
+
+    match read(&RecordStorage::open(&record)?)? {
+        Some(data) => Ok(Some(decoder.decode(&data)?)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME_ID: &str = "40712340";
+    const ENCRYPTION_KEY: &str = "lypnv+b7NqV+3+u3jBjMgwGEeEXm5sa7";
+    const CHUNK: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/decoder/game_data_chunk"
+    ));
+    const DECODED_CHUNK: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/decoder/game_data_chunk.decoded"
+    ));
+
+    #[test]
+    fn test_decode_chunk() {
+        let decoder = ChunkDecoder::new(ENCRYPTION_KEY, GAME_ID).unwrap();
+
+        assert_eq!(decoder.decode(CHUNK).unwrap(), DECODED_CHUNK);
+    }
+
+    #[test]
+    fn test_decode_with_wrong_game_id() {
+        let result =
+            ChunkDecoder::new(ENCRYPTION_KEY, "40712341").and_then(|decoder| decoder.decode(CHUNK));
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_decode_truncated_chunk() {
+        let decoder = ChunkDecoder::new(ENCRYPTION_KEY, GAME_ID).unwrap();
+
+        assert!(matches!(
+            decoder.decode(&CHUNK[..CHUNK.len() - 3]),
+            Err(DecodeError::InvalidLength(_))
+        ));
+    }
+}
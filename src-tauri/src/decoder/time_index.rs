@@ -0,0 +1,220 @@
+use super::block::Blocks;
+use super::error::{BlockError, DecodeError};
+use super::ChunkDecoder;
+use crate::archive::storage::RecordStorage;
+use crate::db::DbError;
+use crate::models::record_time_range::{MediaKind, RecordTimeRange};
+use crate::queries;
+
+use log::debug;
+use serde::Serialize;
+
+/// Seconds between the end of a chunk and the start of the next one above
+/// which game time is considered missing from the record.
+const MAX_CHUNK_GAP: f32 = 2.0;
+
+/// Game time covered by decoded data, from its first to its last block.
+/// `None` when it holds no block.
+pub fn time_range(data: &[u8]) -> Result<Option<(f32, f32)>, BlockError> {
+    let mut range: Option<(f32, f32)> = None;
+
+    for block in Blocks::new(data) {
+        let time = block?.time;
+        range = Some(match range {
+            Some((start, end)) => (start.min(time), end.max(time)),
+            None => (time, time),
+        });
+    }
+
+    Ok(range)
+}
+
+/// Decode every stored chunk and keyframe of a record and store the game time
+/// each one covers. Pieces that cannot be decoded are left out of the index.
+pub fn index_record(record_id: &str) -> Result<TimeIndex, DecodeError> {
+    let record = queries::get_record_by_id(record_id)?
+        .ok_or_else(|| DecodeError::RecordNotFound(record_id.to_string()))?;
+    let decoder = ChunkDecoder::new(&record.encryption_key, &record.game_id)?;
+    let storage = RecordStorage::open(&record)?;
+
+    let mut chunk_ids: Vec<u32> = record.game_data_chunk_ids().into_iter().collect();
+    chunk_ids.sort_unstable();
+    let mut keyframe_ids: Vec<u32> = record.keyframe_ids().into_iter().collect();
+    keyframe_ids.sort_unstable();
+
+    let mut ranges = Vec::new();
+    for (kind, ids) in [
+        (MediaKind::GameDataChunk, chunk_ids),
+        (MediaKind::KeyFrame, keyframe_ids),
+    ] {
+        for id in ids {
+            let data = match kind {
+                MediaKind::GameDataChunk => storage.game_data_chunk(id)?,
+                MediaKind::KeyFrame => storage.keyframe(id)?,
+            };
+            let Some(data) = data else {
+                continue;
+            };
+
+            match decoder
+                .decode(&data)
+                .and_then(|decoded| Ok(time_range(&decoded)?))
+            {
+                Ok(Some((start_time, end_time))) => ranges.push(RecordTimeRange::new(
+                    record.id.clone(),
+                    kind,
+                    id,
+                    start_time,
+                    end_time,
+                )),
+                Ok(None) => {}
+                Err(error) => debug!("Could not index {} {}: {}", kind, id, error),
+            }
+        }
+    }
+
+    queries::save_record_time_ranges(&record.id, &ranges)?;
+
+    Ok(TimeIndex::new(ranges))
+}
+
+/// Stretch of game time missing between two indexed chunks.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TimeHole {
+    pub after_chunk_id: u32,
+    pub before_chunk_id: u32,
+    pub start_time: f32,
+    pub end_time: f32,
+}
+
+/// Game time ranges of the chunks and keyframes of a record, sorted by id.
+#[derive(Serialize, Debug)]
+pub struct TimeIndex {
+    pub chunks: Vec<RecordTimeRange>,
+    pub keyframes: Vec<RecordTimeRange>,
+}
+
+impl TimeIndex {
+    pub fn new(ranges: Vec<RecordTimeRange>) -> Self {
+        let (mut chunks, mut keyframes): (Vec<_>, Vec<_>) = ranges
+            .into_iter()
+            .filter(|range| range.media_kind().is_some())
+            .partition(|range| range.media_kind() == Some(MediaKind::GameDataChunk));
+        chunks.sort_by_key(|range| range.media_id);
+        keyframes.sort_by_key(|range| range.media_id);
+
+        TimeIndex { chunks, keyframes }
+    }
+
+    /// Time index stored for a record.
+    pub fn load(record_id: &str) -> Result<Self, DbError> {
+        Ok(TimeIndex::new(queries::get_record_time_ranges(record_id)?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty() && self.keyframes.is_empty()
+    }
+
+    /// Chunk playing at `time`, or the first one after it when `time` falls
+    /// in a hole.
+    pub fn chunk_at(&self, time: f32) -> Option<u32> {
+        self.chunks
+            .iter()
+            .find(|range| range.contains(time) || range.start_time > time)
+            .map(|range| range.media_id as u32)
+    }
+
+    /// Latest keyframe taken at or before `time`, where playback starting at
+    /// `time` has to load from.
+    pub fn key_frame_before(&self, time: f32) -> Option<u32> {
+        self.keyframes
+            .iter()
+            .rev()
+            .find(|range| range.start_time <= time)
+            .map(|range| range.media_id as u32)
+    }
+
+    /// Game time missing between consecutive chunks, either because chunks
+    /// are missing or because their times do not follow each other.
+    pub fn holes(&self) -> Vec<TimeHole> {
+        self.chunks
+            .windows(2)
+            .filter(|pair| {
+                pair[1].media_id != pair[0].media_id + 1
+                    || pair[1].start_time - pair[0].end_time > MAX_CHUNK_GAP
+            })
+            .map(|pair| TimeHole {
+                after_chunk_id: pair[0].media_id as u32,
+                before_chunk_id: pair[1].media_id as u32,
+                start_time: pair[0].end_time,
+                end_time: pair[1].start_time,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(kind: MediaKind, id: u32, start_time: f32, end_time: f32) -> RecordTimeRange {
+        RecordTimeRange::new("record".to_string(), kind, id, start_time, end_time)
+    }
+
+    fn index() -> TimeIndex {
+        TimeIndex::new(vec![
+            range(MediaKind::GameDataChunk, 1, 0.0, 29.9),
+            range(MediaKind::GameDataChunk, 2, 30.0, 59.9),
+            range(MediaKind::GameDataChunk, 4, 90.0, 119.9),
+            range(MediaKind::GameDataChunk, 5, 135.0, 149.9),
+            range(MediaKind::KeyFrame, 1, 60.0, 60.0),
+            range(MediaKind::KeyFrame, 2, 120.0, 120.0),
+        ])
+    }
+
+    #[test]
+    fn test_time_range() {
+        let mut data = vec![0x00];
+        data.extend(870.5f32.to_le_bytes());
+        data.extend(0u32.to_le_bytes());
+        data.extend(0x0123u16.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        // Relative time, type, parameter and short length
+        data.extend([0xf0, 250, 1, 0, 0xaa]);
+
+        assert_eq!(time_range(&data), Ok(Some((870.5, 870.75))));
+        assert_eq!(time_range(&[]), Ok(None));
+    }
+
+    #[test]
+    fn test_seek() {
+        let index = index();
+
+        assert_eq!(index.chunk_at(45.0), Some(2));
+        assert_eq!(index.chunk_at(70.0), Some(4));
+        assert_eq!(index.chunk_at(200.0), None);
+        assert_eq!(index.key_frame_before(59.0), None);
+        assert_eq!(index.key_frame_before(100.0), Some(1));
+    }
+
+    #[test]
+    fn test_holes() {
+        assert_eq!(
+            index().holes(),
+            vec![
+                TimeHole {
+                    after_chunk_id: 2,
+                    before_chunk_id: 4,
+                    start_time: 59.9,
+                    end_time: 90.0,
+                },
+                TimeHole {
+                    after_chunk_id: 4,
+                    before_chunk_id: 5,
+                    start_time: 119.9,
+                    end_time: 135.0,
+                },
+            ]
+        );
+    }
+}
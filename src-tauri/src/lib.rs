@@ -0,0 +1,13 @@
+pub mod archive;
+pub mod db;
+pub mod decoder;
+pub mod models;
+pub mod queries;
+pub mod recorder;
+pub mod rofl;
+pub mod schema;
+pub mod server;
+pub mod settings;
+#[cfg(test)]
+mod test_utils;
+pub mod verify;
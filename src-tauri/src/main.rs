@@ -2,26 +2,62 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod commands;
-mod db;
-mod models;
-mod queries;
-mod recorder;
-mod schema;
-mod server;
 
+use log::error;
+use pyke_director::recorder::manager::RecorderManager;
+use pyke_director::server::live::LiveSessions;
+use pyke_director::server::spectator::ServerAddress;
+use pyke_director::{db, server};
+use tauri::Manager;
+use tokio::runtime::Handle;
+use tokio::sync::broadcast::error::RecvError;
+
+use std::sync::Arc;
 use std::thread;
 
 fn main() {
     env_logger::init();
 
     tauri::Builder::default()
+        .manage(Arc::new(RecorderManager::with_runtime(
+            tauri::async_runtime::block_on(async { Handle::current() }),
+        )))
+        .manage(Arc::new(LiveSessions::new()))
+        .manage(Arc::new(ServerAddress::new()))
         .setup(|app| {
             let handle = app.handle();
-            let boxed_handle = Box::new(handle);
+            let manager = app.state::<Arc<RecorderManager>>().inner().clone();
+            let live_sessions = app.state::<Arc<LiveSessions>>().inner().clone();
+            let server_address = app.state::<Arc<ServerAddress>>().inner().clone();
+
+            // Forward recording progress to the frontend
+            let mut events = manager.subscribe();
+            tauri::async_runtime::spawn(async move {
+                loop {
+                    match events.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("recording-event", event);
+                        }
+                        Err(RecvError::Lagged(_)) => continue,
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
 
             thread::spawn(move || {
-                db::init();
-                server::spectator::init(*boxed_handle).unwrap();
+                if let Err(error) = db::init() {
+                    error!("Could not open the database: {}", error);
+                    return;
+                }
+                let resumed_manager = manager.clone();
+                tauri::async_runtime::spawn(
+                    async move { resumed_manager.resume_interrupted().await },
+                );
+                if let Err(error) =
+                    server::spectator::init(live_sessions, server_address, Some(manager))
+                {
+                    error!("Replay server stopped: {}", error);
+                }
             });
 
             Ok(())
@@ -29,6 +65,27 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             commands::record_commands::record,
             commands::record_commands::record_custom_endpoint,
+            commands::record_commands::list_active_recordings,
+            commands::record_commands::get_recording_status,
+            commands::record_commands::cancel_recording,
+            commands::record_commands::start_auto_record,
+            commands::record_commands::stop_auto_record,
+            commands::record_commands::list_auto_records,
+            commands::record_commands::list_regions,
+            commands::replay_commands::start_live_replay,
+            commands::replay_commands::stop_live_replay,
+            commands::replay_commands::get_replay_server_address,
+            commands::settings_commands::get_settings,
+            commands::settings_commands::update_settings,
+            commands::archive_commands::export_record,
+            commands::archive_commands::import_record,
+            commands::archive_commands::pack_record,
+            commands::rofl_commands::export_rofl,
+            commands::rofl_commands::import_rofl,
+            commands::decoder_commands::dump_decoded_chunk,
+            commands::decoder_commands::index_record,
+            commands::verify_commands::verify_record,
+            commands::verify_commands::repair_record,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
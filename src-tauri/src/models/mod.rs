@@ -1 +1,3 @@
 pub mod record;
+pub mod record_chunk;
+pub mod record_time_range;
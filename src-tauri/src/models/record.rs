@@ -1,8 +1,15 @@
+use crate::recorder;
+use crate::recorder::api::models::GameMetaData;
 use crate::schema::records;
 
 use chrono::NaiveDateTime;
 use diesel::{Insertable, Queryable};
 use serde::Serialize;
+use uuid::Uuid;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Queryable, Serialize, Insertable)]
 #[diesel(table_name = records)]
@@ -18,4 +25,97 @@ pub struct Record {
     pub game_data_chunks: String,
     pub storage_path: String,
     pub created_at: NaiveDateTime,
+    pub status: String,
+    /// Why the recording failed.
+    pub status_reason: Option<String>,
+}
+
+impl Record {
+    pub fn from_recording(record: &recorder::models::Record, status: RecordStatus) -> Self {
+        let locked_keyframes = record.keyframes.lock().unwrap();
+        let serialized_keyframes = serde_json::to_string(&*locked_keyframes).unwrap();
+
+        let locked_game_data_chunks = record.game_data_chunks.lock().unwrap();
+        let serialized_game_data_chunks = serde_json::to_string(&*locked_game_data_chunks).unwrap();
+
+        Record {
+            id: Uuid::new_v4().to_string(),
+            base_url: record.endpoint.base_url.clone(),
+            platform_id: record.endpoint.platform_id.clone(),
+            version: record.version.clone(),
+            game_id: record.game_id.clone(),
+            encryption_key: record.encryption_key.clone(),
+            metadata: serde_json::to_string(&record.metadata).unwrap(),
+            storage_path: record.storage_path.display().to_string(),
+            keyframes: serialized_keyframes,
+            game_data_chunks: serialized_game_data_chunks,
+            created_at: chrono::Utc::now().naive_utc(),
+            status: status.to_string(),
+            status_reason: None,
+        }
+    }
+
+    pub fn game_meta_data(&self) -> Option<GameMetaData> {
+        serde_json::from_str(&self.metadata).ok()
+    }
+
+    pub fn record_status(&self) -> Option<RecordStatus> {
+        self.status.parse().ok()
+    }
+
+    pub fn game_data_chunk_ids(&self) -> HashSet<u32> {
+        serde_json::from_str(&self.game_data_chunks).unwrap_or_default()
+    }
+
+    pub fn keyframe_ids(&self) -> HashSet<u32> {
+        serde_json::from_str(&self.keyframes).unwrap_or_default()
+    }
+
+    /// Highest game data chunk id stored without any gap from chunk 1.
+    pub fn last_contiguous_game_data_chunk(&self) -> u32 {
+        Self::last_contiguous_id(&self.game_data_chunk_ids())
+    }
+
+    /// Highest keyframe id stored without any gap from keyframe 1.
+    pub fn last_contiguous_keyframe(&self) -> u32 {
+        Self::last_contiguous_id(&self.keyframe_ids())
+    }
+
+    fn last_contiguous_id(ids: &HashSet<u32>) -> u32 {
+        (1..).find(|id| !ids.contains(id)).unwrap() - 1
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RecordStatus {
+    Recording,
+    Complete,
+    Cancelled,
+    Failed,
+}
+
+impl FromStr for RecordStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "recording" => Ok(RecordStatus::Recording),
+            "complete" => Ok(RecordStatus::Complete),
+            "cancelled" => Ok(RecordStatus::Cancelled),
+            "failed" => Ok(RecordStatus::Failed),
+            _ => Err(format!("'{}' is not a valid record status", s)),
+        }
+    }
+}
+
+impl fmt::Display for RecordStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status_str = match self {
+            RecordStatus::Recording => "recording",
+            RecordStatus::Complete => "complete",
+            RecordStatus::Cancelled => "cancelled",
+            RecordStatus::Failed => "failed",
+        };
+        write!(f, "{}", status_str)
+    }
 }
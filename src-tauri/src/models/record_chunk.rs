@@ -0,0 +1,78 @@
+use crate::recorder::api::models::{ChunkInfo, GameMetaData};
+use crate::schema::record_chunks;
+
+use diesel::{Insertable, Queryable};
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+/// Timing and keyframe information the spectator API gave about one game
+/// data chunk while it was recorded. `key_frame_id` is the latest keyframe
+/// available with this chunk and `next_chunk_id` the chunk following it.
+#[derive(Queryable, Serialize, Deserialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = record_chunks)]
+pub struct RecordChunk {
+    pub record_id: String,
+    pub chunk_id: i32,
+    pub duration: Option<i32>,
+    pub received_time: Option<String>,
+    pub key_frame_id: Option<i32>,
+    pub next_chunk_id: Option<i32>,
+}
+
+impl RecordChunk {
+    pub fn new(record_id: String, chunk_id: u32) -> Self {
+        RecordChunk {
+            record_id,
+            chunk_id: chunk_id as i32,
+            duration: None,
+            received_time: None,
+            key_frame_id: None,
+            next_chunk_id: None,
+        }
+    }
+
+    /// Chunk timings and keyframe mapping listed in the game metadata.
+    pub fn from_game_meta_data(record_id: &str, metadata: &GameMetaData) -> Vec<Self> {
+        let mut chunks: BTreeMap<u32, RecordChunk> = BTreeMap::new();
+
+        for info in &metadata.pending_available_chunk_info {
+            let chunk = chunks
+                .entry(info.chunk_id)
+                .or_insert_with(|| RecordChunk::new(record_id.to_string(), info.chunk_id));
+            chunk.duration = Some(info.duration as i32);
+            chunk.received_time = Some(info.received_time.clone());
+        }
+
+        // A keyframe is followed by the chunk it points to
+        for info in &metadata.pending_available_key_frame_info {
+            let chunk = chunks
+                .entry(info.next_chunk_id)
+                .or_insert_with(|| RecordChunk::new(record_id.to_string(), info.next_chunk_id));
+            chunk.key_frame_id = Some(info.key_frame_id as i32);
+            chunk.next_chunk_id = Some(info.next_chunk_id as i32);
+        }
+
+        chunks.into_values().collect()
+    }
+
+    pub fn from_chunk_info(record_id: &str, chunk_info: &ChunkInfo) -> Self {
+        RecordChunk {
+            duration: Some(chunk_info.duration as i32),
+            key_frame_id: Some(chunk_info.key_frame_id as i32),
+            next_chunk_id: Some(chunk_info.next_chunk_id as i32),
+            ..RecordChunk::new(record_id.to_string(), chunk_info.chunk_id)
+        }
+    }
+
+    /// Fill the fields this chunk does not know with the ones of `other`.
+    pub fn merge(self, other: RecordChunk) -> Self {
+        RecordChunk {
+            duration: self.duration.or(other.duration),
+            received_time: self.received_time.or(other.received_time),
+            key_frame_id: self.key_frame_id.or(other.key_frame_id),
+            next_chunk_id: self.next_chunk_id.or(other.next_chunk_id),
+            ..self
+        }
+    }
+}
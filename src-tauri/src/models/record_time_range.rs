@@ -0,0 +1,73 @@
+use crate::schema::record_time_ranges;
+
+use diesel::{Insertable, Queryable};
+use serde::Serialize;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// In-game time covered by one game data chunk or keyframe of a record, in
+/// seconds, from its first to its last block.
+#[derive(Queryable, Serialize, Insertable, Debug, Clone, PartialEq)]
+#[diesel(table_name = record_time_ranges)]
+pub struct RecordTimeRange {
+    pub record_id: String,
+    pub kind: String,
+    pub media_id: i32,
+    pub start_time: f32,
+    pub end_time: f32,
+}
+
+impl RecordTimeRange {
+    pub fn new(
+        record_id: String,
+        kind: MediaKind,
+        media_id: u32,
+        start_time: f32,
+        end_time: f32,
+    ) -> Self {
+        RecordTimeRange {
+            record_id,
+            kind: kind.to_string(),
+            media_id: media_id as i32,
+            start_time,
+            end_time,
+        }
+    }
+
+    pub fn media_kind(&self) -> Option<MediaKind> {
+        self.kind.parse().ok()
+    }
+
+    pub fn contains(&self, time: f32) -> bool {
+        self.start_time <= time && time <= self.end_time
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum MediaKind {
+    GameDataChunk,
+    KeyFrame,
+}
+
+impl FromStr for MediaKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chunk" => Ok(MediaKind::GameDataChunk),
+            "keyframe" => Ok(MediaKind::KeyFrame),
+            _ => Err(format!("'{}' is not a valid media kind", s)),
+        }
+    }
+}
+
+impl fmt::Display for MediaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind_str = match self {
+            MediaKind::GameDataChunk => "chunk",
+            MediaKind::KeyFrame => "keyframe",
+        };
+        write!(f, "{}", kind_str)
+    }
+}
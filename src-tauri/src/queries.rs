@@ -1,24 +1,144 @@
-use crate::db;
-use crate::models::record::Record;
+use crate::db::{self, DbError};
+use crate::models::record::{Record, RecordStatus};
+use crate::models::record_chunk::RecordChunk;
+use crate::models::record_time_range::RecordTimeRange;
+use crate::schema::record_chunks;
+use crate::schema::record_time_ranges;
 use crate::schema::records;
 use crate::schema::records::dsl;
 
 use diesel::prelude::*;
+use diesel::upsert::excluded;
 
-pub fn create_record(new_record: &Record) {
-    let connection = &mut db::establish_db_connection();
+/// Insert the record, or refresh the stored one when the same game was
+/// already being recorded. The original id and creation date are kept and the
+/// id is returned.
+pub fn save_record(record: &Record) -> Result<String, DbError> {
+    let connection = &mut *db::connection()?;
 
     diesel::insert_into(records::table)
-        .values(new_record)
-        .execute(connection)
-        .expect("Error saving new record");
+        .values(record)
+        .on_conflict((dsl::platform_id, dsl::game_id))
+        .do_update()
+        .set((
+            dsl::version.eq(excluded(dsl::version)),
+            dsl::base_url.eq(excluded(dsl::base_url)),
+            dsl::encryption_key.eq(excluded(dsl::encryption_key)),
+            dsl::metadata.eq(excluded(dsl::metadata)),
+            dsl::keyframes.eq(excluded(dsl::keyframes)),
+            dsl::game_data_chunks.eq(excluded(dsl::game_data_chunks)),
+            dsl::storage_path.eq(excluded(dsl::storage_path)),
+            dsl::status.eq(excluded(dsl::status)),
+            dsl::status_reason.eq(excluded(dsl::status_reason)),
+        ))
+        .execute(connection)?;
+
+    Ok(dsl::records
+        .filter(dsl::platform_id.eq(&record.platform_id))
+        .filter(dsl::game_id.eq(&record.game_id))
+        .select(dsl::id)
+        .first::<String>(connection)?)
+}
+
+pub fn get_record(game_id: String) -> Result<Option<Record>, DbError> {
+    let connection = &mut *db::connection()?;
+
+    Ok(dsl::records
+        .filter(dsl::game_id.eq(game_id))
+        .first::<Record>(connection)
+        .optional()?)
 }
 
-pub fn get_record(game_id: String) -> Option<Record> {
-    let connection = &mut db::establish_db_connection();
+/// Record of a game on a platform, game ids are only unique per platform.
+pub fn get_platform_record(platform_id: &str, game_id: &str) -> Result<Option<Record>, DbError> {
+    let connection = &mut *db::connection()?;
 
-    dsl::records
+    Ok(dsl::records
+        .filter(dsl::platform_id.eq(platform_id))
         .filter(dsl::game_id.eq(game_id))
         .first::<Record>(connection)
-        .ok()
+        .optional()?)
+}
+
+pub fn get_record_by_id(record_id: &str) -> Result<Option<Record>, DbError> {
+    let connection = &mut *db::connection()?;
+
+    Ok(dsl::records
+        .find(record_id)
+        .first::<Record>(connection)
+        .optional()?)
+}
+
+pub fn get_records() -> Result<Vec<Record>, DbError> {
+    let connection = &mut *db::connection()?;
+
+    Ok(dsl::records
+        .order(dsl::created_at.desc())
+        .load::<Record>(connection)?)
+}
+
+pub fn get_records_by_status(status: RecordStatus) -> Result<Vec<Record>, DbError> {
+    let connection = &mut *db::connection()?;
+
+    Ok(dsl::records
+        .filter(dsl::status.eq(status.to_string()))
+        .load::<Record>(connection)?)
+}
+
+/// Store chunk information, keeping what was already known about each chunk
+/// when the new information is partial.
+pub fn save_record_chunks(chunks: Vec<RecordChunk>) -> Result<(), DbError> {
+    let connection = &mut *db::connection()?;
+
+    for chunk in chunks {
+        let stored = record_chunks::table
+            .find((&chunk.record_id, chunk.chunk_id))
+            .first::<RecordChunk>(connection)
+            .optional()?;
+        let chunk = match stored {
+            Some(stored) => chunk.merge(stored),
+            None => chunk,
+        };
+
+        diesel::replace_into(record_chunks::table)
+            .values(&chunk)
+            .execute(connection)?;
+    }
+
+    Ok(())
+}
+
+pub fn get_record_chunks(record_id: &str) -> Result<Vec<RecordChunk>, DbError> {
+    let connection = &mut *db::connection()?;
+
+    Ok(record_chunks::table
+        .filter(record_chunks::record_id.eq(record_id))
+        .order(record_chunks::chunk_id)
+        .load::<RecordChunk>(connection)?)
+}
+
+/// Replace the time index of a record.
+pub fn save_record_time_ranges(record_id: &str, ranges: &[RecordTimeRange]) -> Result<(), DbError> {
+    let connection = &mut *db::connection()?;
+
+    connection.transaction(|connection| {
+        diesel::delete(
+            record_time_ranges::table.filter(record_time_ranges::record_id.eq(record_id)),
+        )
+        .execute(connection)?;
+        diesel::insert_into(record_time_ranges::table)
+            .values(ranges)
+            .execute(connection)?;
+
+        Ok(())
+    })
+}
+
+pub fn get_record_time_ranges(record_id: &str) -> Result<Vec<RecordTimeRange>, DbError> {
+    let connection = &mut *db::connection()?;
+
+    Ok(record_time_ranges::table
+        .filter(record_time_ranges::record_id.eq(record_id))
+        .order((record_time_ranges::kind, record_time_ranges::media_id))
+        .load::<RecordTimeRange>(connection)?)
 }
@@ -0,0 +1,196 @@
+use super::error::ApiError;
+use crate::settings::{self, HttpSettings};
+
+use log::debug;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, Response, StatusCode};
+use tokio::time::{sleep, Duration};
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+
+/// Spectator API requests, each one retried with its own budget.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestKind {
+    Version,
+    GameMetaData,
+    LastChunkInfo,
+    GameDataChunk,
+    KeyFrame,
+    EndOfGameStats,
+    FeaturedGames,
+}
+
+impl RequestKind {
+    pub fn retry_policy(self) -> RetryPolicy {
+        match self {
+            RequestKind::Version | RequestKind::GameMetaData | RequestKind::FeaturedGames => {
+                RetryPolicy::new(3, Duration::from_millis(500), Duration::from_secs(5))
+            }
+            // The recording stops once it runs out, so outlast short outages
+            RequestKind::LastChunkInfo => {
+                RetryPolicy::new(10, Duration::from_secs(1), Duration::from_secs(60))
+            }
+            RequestKind::GameDataChunk | RequestKind::KeyFrame | RequestKind::EndOfGameStats => {
+                RetryPolicy::new(5, Duration::from_secs(1), Duration::from_secs(30))
+            }
+        }
+    }
+
+    /// Whether the request is about the game itself rather than one of its
+    /// chunks or keyframes, a 404 then means the game does not exist.
+    fn targets_game(self) -> bool {
+        matches!(self, RequestKind::GameMetaData | RequestKind::LastChunkInfo)
+    }
+}
+
+/// Exponential backoff with jitter: the delay before retry `n` is drawn
+/// between half and all of `base_delay * 2^(n - 1)`, capped at `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub const fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration) -> Self {
+        RetryPolicy {
+            max_attempts,
+            base_delay,
+            max_delay,
+        }
+    }
+
+    /// Delay to wait after the failed attempt `attempt`, starting from 1.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+
+        delay / 2 + delay.mul_f64(jitter() / 2.0)
+    }
+}
+
+/// Random number in `[0, 1)`.
+fn jitter() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Client shared by every spectator API request so connections are pooled.
+/// It is built again when the HTTP settings change.
+pub fn client() -> Client {
+    static CLIENT: Mutex<Option<(HttpSettings, Client)>> = Mutex::new(None);
+
+    let http = settings::current().http;
+    let mut client = CLIENT.lock().unwrap();
+    match &*client {
+        Some((settings, client)) if *settings == http => client.clone(),
+        _ => {
+            let built = build_client(&http);
+            *client = Some((http, built.clone()));
+            built
+        }
+    }
+}
+
+fn build_client(http: &HttpSettings) -> Client {
+    Client::builder()
+        .connect_timeout(Duration::from_secs(http.connect_timeout_secs))
+        .timeout(Duration::from_secs(http.timeout_secs))
+        .user_agent(&http.user_agent)
+        .build()
+        .unwrap_or_else(|error| {
+            debug!("Could not build the HTTP client, using defaults: {}", error);
+            Client::new()
+        })
+}
+
+/// GET `url` and read its body, retrying transient failures as the policy of
+/// `kind` allows. Reading the body is part of each attempt, a connection lost
+/// meanwhile is retried as well.
+pub async fn get(url: &str, kind: RequestKind) -> Result<Vec<u8>, ApiError> {
+    let policy = kind.retry_policy();
+    let client = client();
+    let mut attempt = 1;
+
+    loop {
+        match fetch(&client, url, kind).await {
+            Err(error) if attempt < policy.max_attempts && error.is_transient() => {
+                let delay = match &error {
+                    ApiError::RateLimited {
+                        retry_after: Some(retry_after),
+                    } => *retry_after,
+                    _ => policy.delay(attempt),
+                };
+                debug!(
+                    "{:?} request attempt {} failed: {}, retry in {:?}",
+                    kind, attempt, error, delay
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn fetch(client: &Client, url: &str, kind: RequestKind) -> Result<Vec<u8>, ApiError> {
+    let response = check_status(client.get(url).send().await?, kind)?;
+
+    Ok(response.bytes().await?.to_vec())
+}
+
+fn check_status(response: Response, kind: RequestKind) -> Result<Response, ApiError> {
+    let status = response.status();
+
+    match status {
+        status if status.is_success() => Ok(response),
+        StatusCode::NOT_FOUND if kind.targets_game() => Err(ApiError::GameNotFound),
+        StatusCode::NOT_FOUND => Err(ApiError::NotYetAvailable),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(ApiError::Forbidden(status)),
+        // Never wait longer than the policy would between two attempts
+        StatusCode::TOO_MANY_REQUESTS => Err(ApiError::RateLimited {
+            retry_after: retry_after(&response)
+                .map(|retry_after| retry_after.min(kind.retry_policy().max_delay)),
+        }),
+        status if status.is_server_error() => Err(ApiError::ServerError(status)),
+        status => Err(ApiError::UnexpectedStatus(status)),
+    }
+}
+
+/// `Retry-After` header given in seconds, the HTTP date form is not used by
+/// the spectator API.
+fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let policy = RetryPolicy::new(5, Duration::from_secs(1), Duration::from_secs(5));
+
+        for _ in 0..100 {
+            let delay = policy.delay(1);
+            assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(1));
+
+            let delay = policy.delay(3);
+            assert!(delay >= Duration::from_secs(2) && delay <= Duration::from_secs(4));
+
+            let delay = policy.delay(10);
+            assert!(delay >= Duration::from_millis(2500) && delay <= Duration::from_secs(5));
+        }
+    }
+}
@@ -1,22 +1,24 @@
-use super::models::{ChunkInfo, GameMetaData, SpectatorEndpoint};
+use super::client::{self, RequestKind};
+use super::error::ApiError;
+use super::models::{ChunkInfo, FeaturedGames, GameMetaData, SpectatorEndpoint};
 use log::debug;
-use reqwest;
 
-pub async fn fetch_api_version(endpoint: &SpectatorEndpoint) -> Result<String, reqwest::Error> {
+pub async fn fetch_api_version(endpoint: &SpectatorEndpoint) -> Result<String, ApiError> {
     let url = format!("{}/observer-mode/rest/consumer/version", endpoint.base_url);
 
     debug!("Fetching API version from URL: {}", url);
 
-    let response: String = reqwest::get(&url).await?.error_for_status()?.text().await?;
+    let body = client::get(&url, RequestKind::Version).await?;
+    let response = String::from_utf8_lossy(&body).to_string();
 
     debug!("Received API version response: {}", response);
-    Ok(response.to_string())
+    Ok(response)
 }
 
 pub async fn fetch_game_meta_data(
     endpoint: &SpectatorEndpoint,
     game_id: &str,
-) -> Result<GameMetaData, reqwest::Error> {
+) -> Result<GameMetaData, ApiError> {
     let url = format!(
         "{base_url}/observer-mode/rest/consumer/getGameMetaData/{platform_id}/{game_id}/1/token",
         base_url = endpoint.base_url,
@@ -25,7 +27,8 @@ pub async fn fetch_game_meta_data(
     );
     debug!("Fetching API game meta data from URL: {}", url);
 
-    let response: GameMetaData = reqwest::get(&url).await?.error_for_status()?.json().await?;
+    let body = client::get(&url, RequestKind::GameMetaData).await?;
+    let response: GameMetaData = serde_json::from_slice(&body)?;
 
     debug!("Received API game meta data response: {}", response);
 
@@ -35,7 +38,7 @@ pub async fn fetch_game_meta_data(
 pub async fn fetch_last_chunk_info(
     endpoint: &SpectatorEndpoint,
     game_id: &str,
-) -> Result<ChunkInfo, reqwest::Error> {
+) -> Result<ChunkInfo, ApiError> {
     let url = format!(
         "{base_url}/observer-mode/rest/consumer/getLastChunkInfo/{platform_id}/{game_id}/0/token",
         base_url = endpoint.base_url,
@@ -44,7 +47,8 @@ pub async fn fetch_last_chunk_info(
     );
     debug!("Fetching API last chunk info data from URL: {}", url);
 
-    let response: ChunkInfo = reqwest::get(&url).await?.error_for_status()?.json().await?;
+    let body = client::get(&url, RequestKind::LastChunkInfo).await?;
+    let response: ChunkInfo = serde_json::from_slice(&body)?;
 
     debug!("Received API last chunk info response: {}", response);
 
@@ -55,7 +59,7 @@ pub async fn fetch_game_data_chunk(
     endpoint: &SpectatorEndpoint,
     game_id: &str,
     chunk_id: u32,
-) -> Result<Vec<u8>, reqwest::Error> {
+) -> Result<Vec<u8>, ApiError> {
     let url = format!(
         "{base_url}/observer-mode/rest/consumer/getGameDataChunk/{platform_id}/{game_id}/{chunk_id}/token",
         base_url = endpoint.base_url,
@@ -65,19 +69,18 @@ pub async fn fetch_game_data_chunk(
     );
     debug!("Fetching API game data chunk from URL: {}", url);
 
-    let response = reqwest::get(url).await?.error_for_status()?;
+    let response = client::get(&url, RequestKind::GameDataChunk).await?;
 
     debug!("Received API game data chunk");
 
-    let bytes = response.bytes().await?;
-    Ok(bytes.to_vec())
+    Ok(response)
 }
 
 pub async fn fetch_keyframe(
     endpoint: &SpectatorEndpoint,
     game_id: &str,
     keyframe_id: u32,
-) -> Result<Vec<u8>, reqwest::Error> {
+) -> Result<Vec<u8>, ApiError> {
     let url = format!(
         "{base_url}/observer-mode/rest/consumer/getKeyFrame/{platform_id}/{game_id}/{keyframe_id}/token",
         base_url = endpoint.base_url,
@@ -87,15 +90,43 @@ pub async fn fetch_keyframe(
     );
     debug!("Fetching API keyframe from URL: {}", url);
 
-    let response = reqwest::get(url).await?.error_for_status()?;
+    let response = client::get(&url, RequestKind::KeyFrame).await?;
 
     debug!("Received API keyframe");
 
-    let bytes = response.bytes().await?;
-    Ok(bytes.to_vec())
+    Ok(response)
+}
+
+pub async fn fetch_end_of_game_stats(
+    endpoint: &SpectatorEndpoint,
+    game_id: &str,
+) -> Result<Vec<u8>, ApiError> {
+    let url = format!(
+        "{base_url}/observer-mode/rest/consumer/endOfGameStats/{platform_id}/{game_id}/null",
+        base_url = endpoint.base_url,
+        platform_id = endpoint.platform_id,
+        game_id = game_id
+    );
+    debug!("Fetching API end of game stats from URL: {}", url);
+
+    let response = client::get(&url, RequestKind::EndOfGameStats).await?;
+
+    debug!("Received API end of game stats");
+
+    Ok(response)
 }
 
-// TODO write endOfGameStats and featured endpoints
+pub async fn fetch_featured_games(endpoint: &SpectatorEndpoint) -> Result<FeaturedGames, ApiError> {
+    let url = format!("{}/observer-mode/rest/featured", endpoint.base_url);
+    debug!("Fetching API featured games from URL: {}", url);
+
+    let body = client::get(&url, RequestKind::FeaturedGames).await?;
+    let response: FeaturedGames = serde_json::from_slice(&body)?;
+
+    debug!("Received API featured games response: {}", response);
+
+    Ok(response)
+}
 
 #[cfg(test)]
 mod tests {
@@ -175,6 +206,42 @@ mod tests {
         assert_eq!(chunk_info.next_available_chunk, 0);
     }
 
+    #[tokio::test]
+    async fn test_fetch_unknown_game() {
+        init();
+        let mut server = Server::new_async().await;
+        let _m = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getGameMetaData/KR/6654667050/1/token",
+            )
+            .with_status(404)
+            .create();
+        let _forbidden = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/getKeyFrame/KR/6654667050/1/token",
+            )
+            .with_status(403)
+            .create();
+
+        let endpoint = SpectatorEndpoint {
+            base_url: server.url(),
+            platform_id: "KR".to_string(),
+        };
+
+        let error = fetch_game_meta_data(&endpoint, "6654667050")
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ApiError::GameNotFound));
+        assert!(!error.is_transient());
+
+        let error = fetch_keyframe(&endpoint, "6654667050", 1)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ApiError::Forbidden(_)));
+    }
+
     #[tokio::test]
     async fn test_fetch_game_data_chunk() {
         init();
@@ -222,4 +289,57 @@ mod tests {
         let result = fetch_keyframe(&endpoint, "6654667050", 1).await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_fetch_end_of_game_stats() {
+        init();
+        let mut server = Server::new_async().await;
+        let mock_data = b"mocked binary data";
+        let _m = server
+            .mock(
+                "GET",
+                "/observer-mode/rest/consumer/endOfGameStats/KR/6654667050/null",
+            )
+            .with_status(200)
+            .with_header("content-type", "application/octet-stream")
+            .with_body(mock_data)
+            .create();
+
+        let endpoint = SpectatorEndpoint {
+            base_url: server.url(),
+            platform_id: "KR".to_string(),
+        };
+
+        let result = fetch_end_of_game_stats(&endpoint, "6654667050").await;
+        assert_eq!(result.unwrap(), mock_data.to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_featured_games() {
+        init();
+        let mut server = Server::new_async().await;
+        let _m = server
+            .mock("GET", "/observer-mode/rest/featured")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"gameList":[{"gameId":6654667050,"mapId":11,"gameMode":"CLASSIC","gameType":"MATCHED_GAME","gameQueueConfigId":420,"participants":[{"teamId":100,"spell1Id":4,"spell2Id":14,"championId":238,"skinIndex":0,"profileIconId":4568,"summonerName":"Faker","bot":false}],"observers":{"encryptionKey":"3Fh7kJzQ1zXm9yXq0JbE0d2dGcQmYw6P"},"platformId":"KR","bannedChampions":[{"championId":157,"teamId":100,"pickTurn":1}],"gameStartTime":1692096102000,"gameLength":312}],"clientRefreshInterval":300}"#)
+            .create();
+
+        let endpoint = SpectatorEndpoint {
+            base_url: server.url(),
+            platform_id: "KR".to_string(),
+        };
+
+        let featured_games = fetch_featured_games(&endpoint).await.unwrap();
+        assert_eq!(featured_games.client_refresh_interval, 300);
+        assert_eq!(featured_games.game_list.len(), 1);
+
+        let game = &featured_games.game_list[0];
+        assert_eq!(game.game_id, 6654667050);
+        assert_eq!(game.game_queue_config_id, 420);
+        assert_eq!(
+            game.observers.encryption_key,
+            "3Fh7kJzQ1zXm9yXq0JbE0d2dGcQmYw6P"
+        );
+    }
 }
@@ -0,0 +1,61 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+use tokio::time::Duration;
+
+/// Why a spectator API request failed.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("game not found")]
+    GameNotFound,
+
+    #[error("not available yet")]
+    NotYetAvailable,
+
+    #[error("access denied with status {0}")]
+    Forbidden(StatusCode),
+
+    #[error("rate limited")]
+    RateLimited {
+        /// Delay the `Retry-After` header asks for.
+        retry_after: Option<Duration>,
+    },
+
+    #[error("server error with status {0}")]
+    ServerError(StatusCode),
+
+    #[error("unexpected status {0}")]
+    UnexpectedStatus(StatusCode),
+
+    #[error("invalid response: {0}")]
+    Decode(reqwest::Error),
+
+    #[error("invalid response: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("network error: {0}")]
+    Network(reqwest::Error),
+}
+
+impl ApiError {
+    /// Whether the same request can succeed later. Fatal errors mean the game
+    /// cannot be recorded at all.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            ApiError::NotYetAvailable
+                | ApiError::RateLimited { .. }
+                | ApiError::ServerError(_)
+                | ApiError::Network(_)
+        )
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_decode() {
+            ApiError::Decode(error)
+        } else {
+            ApiError::Network(error)
+        }
+    }
+}
@@ -1,2 +1,5 @@
+pub mod client;
 pub mod endpoints;
+pub mod error;
 pub mod models;
+pub mod regions;
@@ -1,7 +1,9 @@
 use std::fmt;
 use std::str::FromStr;
 
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::regions;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct SpectatorEndpoint {
@@ -26,14 +28,48 @@ impl fmt::Display for SpectatorEndpoint {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Region {
-    KR,
+    BR1,
+    EUN1,
     EUW1,
+    JP1,
+    KR,
+    LA1,
+    LA2,
     NA1,
+    OC1,
+    TR1,
+    RU,
+    PH2,
+    SG2,
+    TH2,
+    TW2,
+    VN2,
+    PBE1,
 }
 
 impl Region {
+    pub const ALL: [Region; 17] = [
+        Region::BR1,
+        Region::EUN1,
+        Region::EUW1,
+        Region::JP1,
+        Region::KR,
+        Region::LA1,
+        Region::LA2,
+        Region::NA1,
+        Region::OC1,
+        Region::TR1,
+        Region::RU,
+        Region::PH2,
+        Region::SG2,
+        Region::TH2,
+        Region::TW2,
+        Region::VN2,
+        Region::PBE1,
+    ];
+
     pub fn to_endpoint(&self) -> SpectatorEndpoint {
         SpectatorEndpoint {
             base_url: self.base_url(),
@@ -43,8 +79,10 @@ impl Region {
     fn platform_id(&self) -> String {
         self.to_string().to_uppercase()
     }
+    /// Spectator host of the platform, unless the regions file overrides it.
     fn base_url(&self) -> String {
-        format!("http://spectator-consumer.{}.lol.pvp.net:80", self)
+        regions::base_url_override(&self.platform_id())
+            .unwrap_or_else(|| format!("http://spectator-consumer.{}.lol.pvp.net:80", self))
     }
 }
 
@@ -52,26 +90,59 @@ impl FromStr for Region {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "kr" => Ok(Region::KR),
-            "euw1" => Ok(Region::EUW1),
-            "na1" => Ok(Region::NA1),
-            _ => Err(format!("'{}' is not a valid region", s)),
-        }
+        Region::ALL
+            .into_iter()
+            .find(|region| region.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("'{}' is not a valid region", s))
     }
 }
 
 impl fmt::Display for Region {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let region_str = match self {
-            Region::KR => "kr",
+            Region::BR1 => "br1",
+            Region::EUN1 => "eun1",
             Region::EUW1 => "euw1",
+            Region::JP1 => "jp1",
+            Region::KR => "kr",
+            Region::LA1 => "la1",
+            Region::LA2 => "la2",
             Region::NA1 => "na1",
+            Region::OC1 => "oc1",
+            Region::TR1 => "tr1",
+            Region::RU => "ru",
+            Region::PH2 => "ph2",
+            Region::SG2 => "sg2",
+            Region::TH2 => "th2",
+            Region::TW2 => "tw2",
+            Region::VN2 => "vn2",
+            Region::PBE1 => "pbe1",
         };
         write!(f, "{}", region_str)
     }
 }
 
+// Regions go through `FromStr` and `Display` so "kr", "KR" and "Kr" are all
+// accepted and a region always serializes the same way
+impl<'de> Deserialize<'de> for Region {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Region {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GameMetaData {
@@ -219,3 +290,97 @@ impl fmt::Display for ChunkInfo {
         Ok(())
     }
 }
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeaturedGames {
+    pub game_list: Vec<FeaturedGame>,
+    pub client_refresh_interval: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeaturedGame {
+    pub game_id: u64,
+    pub map_id: u32,
+    pub game_mode: String,
+    pub game_type: String,
+    pub game_queue_config_id: u32,
+    pub participants: Vec<FeaturedGameParticipant>,
+    pub observers: FeaturedGameObservers,
+    pub platform_id: String,
+    #[serde(default)]
+    pub banned_champions: Vec<BannedChampion>,
+    pub game_start_time: u64,
+    pub game_length: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeaturedGameParticipant {
+    pub team_id: u32,
+    pub spell1_id: u32,
+    pub spell2_id: u32,
+    pub champion_id: u32,
+    pub skin_index: u32,
+    pub profile_icon_id: u32,
+    pub summoner_name: String,
+    pub bot: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeaturedGameObservers {
+    pub encryption_key: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BannedChampion {
+    pub champion_id: i32,
+    pub team_id: u32,
+    pub pick_turn: u32,
+}
+
+impl fmt::Display for FeaturedGames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Client Refresh Interval: {}",
+            self.client_refresh_interval
+        )?;
+        writeln!(f, "Games:")?;
+        for game in &self.game_list {
+            writeln!(
+                f,
+                "\tGame ID: {}, Platform ID: {}, Queue: {}, Game Length: {}",
+                game.game_id, game.platform_id, game.game_queue_config_id, game.game_length
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_parsing_is_case_insensitive() {
+        assert_eq!("euw1".parse::<Region>(), Ok(Region::EUW1));
+        assert_eq!("EUW1".parse::<Region>(), Ok(Region::EUW1));
+        assert!("euw2".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn test_region_serde_round_trip() {
+        for region in Region::ALL {
+            let serialized = serde_json::to_string(&region).unwrap();
+            let deserialized: Region = serde_json::from_str(&serialized).unwrap();
+            assert_eq!(deserialized, region);
+        }
+
+        let region: Region = serde_json::from_str(r#""KR""#).unwrap();
+        assert_eq!(region, Region::KR);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::settings;
+
+use log::debug;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// The regions file maps platform ids to the spectator base url to use
+/// instead of the default one, for when Riot moves a spectator host:
+///
+/// ```json
+/// { "KR": "http://spectator-consumer.kr.lol.pvp.net:80" }
+/// ```
+fn get_regions_file_path() -> PathBuf {
+    settings::get_config_dir().join("regions.json")
+}
+
+pub fn base_url_override(platform_id: &str) -> Option<String> {
+    load_base_url_overrides()
+        .into_iter()
+        .find(|(override_platform_id, _)| override_platform_id.eq_ignore_ascii_case(platform_id))
+        .map(|(_, base_url)| base_url)
+}
+
+fn load_base_url_overrides() -> HashMap<String, String> {
+    let path = get_regions_file_path();
+    let Ok(content) = fs::read_to_string(&path) else {
+        return HashMap::new();
+    };
+
+    serde_json::from_str(&content).unwrap_or_else(|error| {
+        debug!(
+            "Ignoring invalid regions file {}: {}",
+            path.display(),
+            error
+        );
+        HashMap::new()
+    })
+}
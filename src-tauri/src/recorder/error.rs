@@ -1,14 +1,22 @@
-use reqwest;
+use super::api::error::ApiError;
+use crate::db::DbError;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum RecordingError {
-    #[error("network error: {0}")]
-    NetworkError(#[from] reqwest::Error),
+    #[error("spectator API error: {0}")]
+    ApiError(#[from] ApiError),
 
     #[error("IO error occurred: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("could not access the database: {0}")]
+    Database(#[from] DbError),
+
     #[error("failed to unwrap Arc")]
     ArcUnwrapError,
+
+    #[error("game {0} is already being recorded")]
+    AlreadyRecording(String),
 }
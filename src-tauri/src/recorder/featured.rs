@@ -0,0 +1,109 @@
+use super::api::endpoints;
+use super::api::models::{FeaturedGame, SpectatorEndpoint};
+use super::manager::RecorderManager;
+use crate::db;
+use crate::queries;
+
+use log::debug;
+use serde::Deserialize;
+use tokio::time::{sleep, Duration};
+use tokio_util::sync::CancellationToken;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Which featured games the auto-record mode should pick.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AutoRecordFilter {
+    /// Queue ids to record, every queue when empty.
+    #[serde(default)]
+    pub queue_ids: Vec<u32>,
+    /// Minimum interest score from the game metadata.
+    pub min_interest_score: Option<u32>,
+}
+
+impl AutoRecordFilter {
+    fn accepts_queue(&self, game: &FeaturedGame) -> bool {
+        self.queue_ids.is_empty() || self.queue_ids.contains(&game.game_queue_config_id)
+    }
+}
+
+/// Poll the featured games of `endpoint` and start a recording for every new
+/// game matching `filter`, until `cancellation` is triggered.
+pub async fn auto_record(
+    manager: Arc<RecorderManager>,
+    endpoint: SpectatorEndpoint,
+    filter: AutoRecordFilter,
+    storage_path: PathBuf,
+    cancellation: CancellationToken,
+) {
+    while !cancellation.is_cancelled() {
+        let refresh_interval = match endpoints::fetch_featured_games(&endpoint).await {
+            Ok(featured_games) => {
+                for game in featured_games.game_list {
+                    record_featured_game(&manager, &endpoint, &filter, &storage_path, game).await;
+                }
+                Duration::from_secs(featured_games.client_refresh_interval.max(1))
+            }
+            Err(error) => {
+                debug!("Featured games received error {} retry later", error);
+                Duration::from_secs(60)
+            }
+        };
+
+        tokio::select! {
+            _ = sleep(refresh_interval) => {}
+            _ = cancellation.cancelled() => {}
+        }
+    }
+}
+
+async fn record_featured_game(
+    manager: &Arc<RecorderManager>,
+    endpoint: &SpectatorEndpoint,
+    filter: &AutoRecordFilter,
+    storage_path: &Path,
+    game: FeaturedGame,
+) {
+    let game_id = game.game_id.to_string();
+
+    if !filter.accepts_queue(&game) || manager.status(&endpoint.platform_id, &game_id).is_some() {
+        return;
+    }
+    let stored_record = {
+        let platform_id = endpoint.platform_id.clone();
+        let game_id = game_id.clone();
+        db::blocking(move || queries::get_platform_record(&platform_id, &game_id)).await
+    };
+    // A stored game was recorded, cancelled by the user or failed, and an
+    // interrupted one is resumed on start, none is picked up again
+    match stored_record {
+        Ok(Some(_)) => return,
+        Ok(None) => {}
+        Err(error) => {
+            debug!("Skipping featured game {}: {}", game_id, error);
+            return;
+        }
+    }
+
+    if let Some(min_interest_score) = filter.min_interest_score {
+        match endpoints::fetch_game_meta_data(endpoint, &game_id).await {
+            Ok(metadata) if metadata.interest_score >= min_interest_score => {}
+            Ok(_) => return,
+            Err(error) => {
+                debug!("Skipping featured game {}: {}", game_id, error);
+                return;
+            }
+        }
+    }
+
+    debug!("Auto recording featured game {}", game_id);
+    if let Err(error) = manager.start(
+        endpoint.clone(),
+        game_id,
+        game.observers.encryption_key,
+        storage_path.to_path_buf(),
+    ) {
+        debug!("Could not auto record featured game: {}", error);
+    }
+}
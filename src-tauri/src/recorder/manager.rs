@@ -0,0 +1,249 @@
+use super::api::models::SpectatorEndpoint;
+use super::error::RecordingError;
+use super::featured::{self, AutoRecordFilter};
+use super::models::{RecordingEvent, RecordingHandle, RecordingState, RecordingStatus};
+use super::process;
+use crate::db;
+use crate::models::record::{Record as StoredRecord, RecordStatus};
+use crate::queries;
+
+use log::debug;
+use tokio::runtime::Handle;
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+type RecordingKey = (String, String);
+
+const EVENTS_CAPACITY: usize = 256;
+
+/// Registry of the recordings currently running, keyed by
+/// (platform_id, game_id).
+pub struct RecorderManager {
+    recordings: Mutex<HashMap<RecordingKey, RecordingHandle>>,
+    events: broadcast::Sender<RecordingEvent>,
+    auto_records: Mutex<HashMap<String, CancellationToken>>,
+    runtime: Option<Handle>,
+}
+
+impl Default for RecorderManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RecorderManager {
+    /// Recordings run on the runtime of the caller starting them.
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+
+        RecorderManager {
+            recordings: Mutex::new(HashMap::new()),
+            events,
+            auto_records: Mutex::new(HashMap::new()),
+            runtime: None,
+        }
+    }
+
+    /// Recordings run on `runtime` whoever starts them, e.g. the replay server
+    /// workers for the control API.
+    pub fn with_runtime(runtime: Handle) -> Self {
+        RecorderManager {
+            runtime: Some(runtime),
+            ..Self::new()
+        }
+    }
+
+    fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match &self.runtime {
+            Some(runtime) => runtime.spawn(future),
+            None => tokio::spawn(future),
+        }
+    }
+
+    /// Receive the progress events of every recording.
+    pub fn subscribe(&self) -> broadcast::Receiver<RecordingEvent> {
+        self.events.subscribe()
+    }
+
+    /// Spawn a new recording and return its initial status.
+    pub fn start(
+        self: &Arc<Self>,
+        endpoint: SpectatorEndpoint,
+        game_id: String,
+        encryption_key: String,
+        storage_path: PathBuf,
+    ) -> Result<RecordingStatus, RecordingError> {
+        let key = (endpoint.platform_id.clone(), game_id.clone());
+        // Keep the registry locked until the task is spawned and registered so
+        // a recording finishing early cannot unregister itself before that
+        let mut recordings = self.recordings.lock().unwrap();
+
+        if recordings.contains_key(&key) {
+            return Err(RecordingError::AlreadyRecording(format!(
+                "{}_{}",
+                key.0, key.1
+            )));
+        }
+
+        let handle = RecordingHandle::new(key.0.clone(), key.1.clone(), self.events.clone());
+        let task_handle = handle.clone();
+        let manager = self.clone();
+        let task_key = key.clone();
+
+        self.spawn(async move {
+            let result = process::new(
+                endpoint,
+                game_id,
+                encryption_key,
+                storage_path,
+                task_handle.clone(),
+            )
+            .await;
+
+            task_handle.update(|status| match result {
+                Ok(_) if task_handle.is_cancelled() => status.state = RecordingState::Cancelled,
+                Ok(_) => status.state = RecordingState::Finished,
+                Err(error) => {
+                    debug!("Recording {}_{} failed: {}", task_key.0, task_key.1, error);
+                    status.state = RecordingState::Failed;
+                    status.error = Some(error.to_string());
+                }
+            });
+            manager.recordings.lock().unwrap().remove(&task_key);
+        });
+
+        let status = handle.status();
+        recordings.insert(key, handle);
+
+        Ok(status)
+    }
+
+    /// Restart a recording that is stored in the database but not finished.
+    pub fn resume(
+        self: &Arc<Self>,
+        stored_record: StoredRecord,
+    ) -> Result<RecordingStatus, RecordingError> {
+        let endpoint = SpectatorEndpoint::new(stored_record.base_url, stored_record.platform_id);
+        // The stored path is the game directory, `Record::new` expects its parent
+        let storage_path = Path::new(&stored_record.storage_path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        self.start(
+            endpoint,
+            stored_record.game_id,
+            stored_record.encryption_key,
+            storage_path,
+        )
+    }
+
+    /// Resume every recording the database still marks as in progress.
+    pub async fn resume_interrupted(self: &Arc<Self>) {
+        let stored_records =
+            match db::blocking(|| queries::get_records_by_status(RecordStatus::Recording)).await {
+                Ok(stored_records) => stored_records,
+                Err(error) => {
+                    debug!("Could not load interrupted recordings: {}", error);
+                    return;
+                }
+            };
+
+        for stored_record in stored_records {
+            debug!(
+                "Resuming interrupted recording {}_{}",
+                stored_record.platform_id, stored_record.game_id
+            );
+            if let Err(error) = self.resume(stored_record) {
+                debug!("Resuming recording failed: {}", error);
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<RecordingStatus> {
+        self.recordings
+            .lock()
+            .unwrap()
+            .values()
+            .map(RecordingHandle::status)
+            .collect()
+    }
+
+    pub fn status(&self, platform_id: &str, game_id: &str) -> Option<RecordingStatus> {
+        self.recordings
+            .lock()
+            .unwrap()
+            .get(&(platform_id.to_string(), game_id.to_string()))
+            .map(RecordingHandle::status)
+    }
+
+    /// Ask a recording to stop. It stores what it has downloaded so far and is
+    /// removed from the registry once its task ends.
+    pub fn cancel(&self, platform_id: &str, game_id: &str) -> bool {
+        match self
+            .recordings
+            .lock()
+            .unwrap()
+            .get(&(platform_id.to_string(), game_id.to_string()))
+        {
+            Some(handle) => {
+                handle.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Record every featured game of the endpoint platform matching `filter`,
+    /// replacing any auto-record mode already running for that platform.
+    pub fn start_auto_record(
+        self: &Arc<Self>,
+        endpoint: SpectatorEndpoint,
+        filter: AutoRecordFilter,
+        storage_path: PathBuf,
+    ) {
+        let cancellation = CancellationToken::new();
+
+        if let Some(previous) = self
+            .auto_records
+            .lock()
+            .unwrap()
+            .insert(endpoint.platform_id.clone(), cancellation.clone())
+        {
+            previous.cancel();
+        }
+
+        self.spawn(featured::auto_record(
+            self.clone(),
+            endpoint,
+            filter,
+            storage_path,
+            cancellation,
+        ));
+    }
+
+    /// Stop looking for new featured games, recordings already started go on.
+    pub fn stop_auto_record(&self, platform_id: &str) -> bool {
+        match self.auto_records.lock().unwrap().remove(platform_id) {
+            Some(cancellation) => {
+                cancellation.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list_auto_records(&self) -> Vec<String> {
+        self.auto_records.lock().unwrap().keys().cloned().collect()
+    }
+}
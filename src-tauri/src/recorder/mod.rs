@@ -1,4 +1,7 @@
 pub mod api;
 pub mod error;
+pub mod featured;
+pub mod manager;
 pub mod models;
 pub mod process;
+pub mod scheduler;
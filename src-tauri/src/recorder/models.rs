@@ -1,15 +1,18 @@
 use super::api::models::{GameMetaData, SpectatorEndpoint};
 
+use chrono::NaiveDateTime;
 use serde::ser::SerializeStruct;
 use serde::{Deserialize, Serialize, Serializer};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 
 use std::collections::HashSet;
 use std::fmt;
 use std::fs;
 use std::io;
 use std::io::ErrorKind;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 pub struct Record {
     pub version: String,
@@ -20,6 +23,7 @@ pub struct Record {
     pub storage_path: PathBuf,
     pub keyframes: Mutex<HashSet<u32>>,
     pub game_data_chunks: Mutex<HashSet<u32>>,
+    pub handle: RecordingHandle,
 }
 
 impl Record {
@@ -29,23 +33,51 @@ impl Record {
         game_id: String,
         encryption_key: String,
         base_path: PathBuf,
+        handle: RecordingHandle,
     ) -> Result<Self, io::Error> {
         let storage_path = base_path.join(format!("{}_{}", endpoint.platform_id, game_id));
         Self::create_dir_if_not_exists(storage_path.join("game_data_chunks"))?;
         Self::create_dir_if_not_exists(storage_path.join("keyframes"))?;
 
+        // Pick up what a previous, interrupted recording already stored
+        let game_data_chunks = Self::read_stored_ids(storage_path.join("game_data_chunks"))?;
+        let keyframes = Self::read_stored_ids(storage_path.join("keyframes"))?;
+
         Ok(Record {
             version,
             endpoint,
             game_id,
             encryption_key,
             metadata: None,
-            keyframes: Mutex::new(HashSet::new()),
-            game_data_chunks: Mutex::new(HashSet::new()),
+            keyframes: Mutex::new(keyframes),
+            game_data_chunks: Mutex::new(game_data_chunks),
             storage_path,
+            handle,
         })
     }
 
+    fn read_stored_ids(path: PathBuf) -> Result<HashSet<u32>, io::Error> {
+        let mut ids = HashSet::new();
+
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            // Skip files left empty by a write that was interrupted, files
+            // written since are renamed into place once complete
+            if entry.metadata()?.len() == 0 {
+                continue;
+            }
+            if let Some(id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<u32>().ok())
+            {
+                ids.insert(id);
+            }
+        }
+
+        Ok(ids)
+    }
+
     fn create_dir_if_not_exists(path: PathBuf) -> Result<(), io::Error> {
         match fs::create_dir_all(&path) {
             Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(()), // If it already exists, just return Ok
@@ -69,19 +101,60 @@ impl Record {
         self.keyframes.lock().unwrap().insert(chunk_id);
     }
 
+    /// First game data chunk id, starting from 1, that is not stored yet.
+    pub fn next_missing_game_data_chunk(&self) -> u32 {
+        Self::next_missing_id(&self.game_data_chunks.lock().unwrap())
+    }
+
+    /// First keyframe id, starting from 1, that is not stored yet.
+    pub fn next_missing_keyframe(&self) -> u32 {
+        Self::next_missing_id(&self.keyframes.lock().unwrap())
+    }
+
+    fn next_missing_id(ids: &HashSet<u32>) -> u32 {
+        (1..).find(|id| !ids.contains(id)).unwrap()
+    }
+
+    /// Refresh the stored counts of the recording status.
+    pub fn report_progress(&self) {
+        let game_data_chunks = self.game_data_chunks.lock().unwrap().len();
+        let keyframes = self.keyframes.lock().unwrap().len();
+
+        self.handle.update(|status| {
+            status.game_data_chunks = game_data_chunks;
+            status.keyframes = keyframes;
+        });
+    }
+
     pub fn store_game_data_chunk(&self, chunk_id: u32, data: Vec<u8>) -> Result<(), io::Error> {
         let path = self
             .storage_path
             .join(format!("game_data_chunks/{}", chunk_id));
 
-        fs::write(path, data)
+        store_file(&path, &data)
     }
 
     pub fn store_key_frame(&self, keyframe_id: u32, data: Vec<u8>) -> Result<(), io::Error> {
         let path = self.storage_path.join(format!("keyframes/{}", keyframe_id));
 
-        fs::write(path, data)
+        store_file(&path, &data)
     }
+
+    pub fn store_end_of_game_stats(&self, data: Vec<u8>) -> Result<(), io::Error> {
+        let path = self.storage_path.join("end_of_game_stats");
+
+        store_file(&path, &data)
+    }
+}
+
+/// Write `data` to `<path>.tmp` then rename it to `path`, so an interrupted
+/// write never leaves a truncated file that looks stored.
+pub fn store_file(path: &Path, data: &[u8]) -> Result<(), io::Error> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    fs::write(&temp_path, data)?;
+    fs::rename(&temp_path, path)
 }
 
 impl Serialize for Record {
@@ -136,3 +209,165 @@ impl fmt::Display for Record {
         Ok(())
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingState {
+    Starting,
+    Recording,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingStatus {
+    pub platform_id: String,
+    pub game_id: String,
+    pub state: RecordingState,
+    pub game_data_chunks: usize,
+    pub keyframes: usize,
+    pub last_chunk_id: u32,
+    pub end_game_chunk_id: u32,
+    /// Chunks and keyframes whose download failed and has not succeeded since.
+    pub failed_downloads: usize,
+    pub error: Option<String>,
+    pub started_at: NaiveDateTime,
+}
+
+/// Progress notification sent while a recording runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingEvent {
+    pub platform_id: String,
+    pub game_id: String,
+    #[serde(flatten)]
+    pub kind: RecordingEventKind,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordingEventKind {
+    Started,
+    MetadataFetched {
+        last_chunk_id: u32,
+        last_key_frame_id: u32,
+    },
+    ChunkStored {
+        chunk_id: u32,
+        game_data_chunks: usize,
+    },
+    KeyframeStored {
+        keyframe_id: u32,
+        keyframes: usize,
+    },
+    BackfillStarted {
+        chunk_id: u32,
+        keyframe_id: u32,
+    },
+    BackfillFinished,
+    FetchError {
+        message: String,
+    },
+    GameEnded {
+        end_game_chunk_id: u32,
+    },
+    Saved {
+        status: String,
+    },
+}
+
+/// Shared view on a running recording, used to follow its progress and to
+/// cancel it from outside the recording task.
+#[derive(Clone)]
+pub struct RecordingHandle {
+    status: Arc<Mutex<RecordingStatus>>,
+    cancellation: CancellationToken,
+    events: broadcast::Sender<RecordingEvent>,
+}
+
+impl RecordingHandle {
+    pub fn new(
+        platform_id: String,
+        game_id: String,
+        events: broadcast::Sender<RecordingEvent>,
+    ) -> Self {
+        RecordingHandle {
+            status: Arc::new(Mutex::new(RecordingStatus {
+                platform_id,
+                game_id,
+                state: RecordingState::Starting,
+                game_data_chunks: 0,
+                keyframes: 0,
+                last_chunk_id: 0,
+                end_game_chunk_id: 0,
+                failed_downloads: 0,
+                error: None,
+                started_at: chrono::Utc::now().naive_utc(),
+            })),
+            cancellation: CancellationToken::new(),
+            events,
+        }
+    }
+
+    pub fn emit(&self, kind: RecordingEventKind) {
+        let (platform_id, game_id) = {
+            let status = self.status.lock().unwrap();
+            (status.platform_id.clone(), status.game_id.clone())
+        };
+
+        // Nobody listening is not an error, events are only informative
+        let _ = self.events.send(RecordingEvent {
+            platform_id,
+            game_id,
+            kind,
+        });
+    }
+
+    pub fn status(&self) -> RecordingStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut RecordingStatus),
+    {
+        f(&mut self.status.lock().unwrap());
+    }
+
+    pub fn cancel(&self) {
+        self.cancellation.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Resolves once the recording has been cancelled.
+    pub async fn cancelled(&self) {
+        self.cancellation.cancelled().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_dir;
+
+    #[test]
+    fn test_stored_ids_skip_partial_files() {
+        let dir = test_dir("stored-ids");
+
+        store_file(&dir.join("1"), b"chunk").unwrap();
+        fs::write(dir.join("2.tmp"), b"partial").unwrap();
+        fs::write(dir.join("3"), b"").unwrap();
+
+        assert_eq!(fs::read(dir.join("1")).unwrap(), b"chunk");
+        assert!(!dir.join("1.tmp").exists());
+        assert_eq!(
+            Record::read_stored_ids(dir.clone()).unwrap(),
+            HashSet::from([1])
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
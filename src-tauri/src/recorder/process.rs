@@ -1,75 +1,238 @@
 use super::api::endpoints;
+use super::api::error::ApiError;
 use super::api::models::SpectatorEndpoint;
 use super::error::RecordingError;
-use super::models::Record;
+use super::models::{Record, RecordingEventKind, RecordingHandle, RecordingState};
+use super::scheduler::{DownloadScheduler, MediaItem, Priority};
+use crate::decoder::time_index;
+use crate::models::record::{Record as StoredRecord, RecordStatus};
+use crate::models::record_chunk::RecordChunk;
+use crate::{db, queries, settings};
 
 use log::debug;
 use tokio::spawn;
+use tokio::task::spawn_blocking;
 use tokio::time::{sleep, Duration};
 
 use std::path::PathBuf;
 use std::sync::Arc;
 
+/// Consecutive failed polls of the last chunk info, each one after the retries
+/// of the request itself, before the recording gives up.
+const MAX_POLL_FAILURES: u32 = 3;
+
+/// How long the spectator API keeps serving a game once it ended, failed
+/// downloads are retried until then.
+const AVAILABILITY_WINDOW: Duration = Duration::from_secs(10 * 60);
+
 pub async fn new(
     endpoint: SpectatorEndpoint,
     game_id: String,
     encryption_key: String,
     storage_path: PathBuf,
+    handle: RecordingHandle,
 ) -> Result<Record, RecordingError> {
-    let version = endpoints::fetch_api_version(&endpoint).await?;
-    let mut record = Record::new(version, endpoint, game_id, encryption_key, storage_path)?;
+    handle.emit(RecordingEventKind::Started);
+
+    let version = match endpoints::fetch_api_version(&endpoint).await {
+        Ok(version) => version,
+        Err(error) => return Err(fail_stored_record(&endpoint, &game_id, error).await.into()),
+    };
+    let mut record = Record::new(
+        version,
+        endpoint,
+        game_id,
+        encryption_key,
+        storage_path,
+        handle,
+    )?;
 
-    let metadata = endpoints::fetch_game_meta_data(&record.endpoint, &record.game_id).await?;
+    let metadata = match endpoints::fetch_game_meta_data(&record.endpoint, &record.game_id).await {
+        Ok(metadata) => metadata,
+        Err(error) => {
+            return Err(fail_stored_record(&record.endpoint, &record.game_id, error)
+                .await
+                .into())
+        }
+    };
+    record.handle.emit(RecordingEventKind::MetadataFetched {
+        last_chunk_id: metadata.last_chunk_id,
+        last_key_frame_id: metadata.last_key_frame_id,
+    });
     record.metadata = Some(metadata);
 
+    // Save the record right away so it can be resumed if the app stops mid-game
+    let record_id = save_record(&record, RecordStatus::Recording, None).await?;
+    save_meta_data_chunks(&record_id, &record).await;
+    record
+        .handle
+        .update(|status| status.state = RecordingState::Recording);
+    record.report_progress();
+
     let arc_record = Arc::new(record);
 
-    record_media_data(arc_record).await
+    let mut record = record_media_data(arc_record, &record_id).await?;
+    let status = if record.handle.is_cancelled() {
+        RecordStatus::Cancelled
+    } else {
+        // Once the game is over the metadata holds the end game chunk and keyframe
+        match endpoints::fetch_game_meta_data(&record.endpoint, &record.game_id).await {
+            Ok(metadata) => {
+                record.metadata = Some(metadata);
+                save_meta_data_chunks(&record_id, &record).await;
+            }
+            Err(error) => debug!("Could not refresh game meta data: {}", error),
+        }
+        // Needed by the client post-game screen when replaying
+        match endpoints::fetch_end_of_game_stats(&record.endpoint, &record.game_id).await {
+            Ok(stats) => {
+                if let Err(e) = record.store_end_of_game_stats(stats) {
+                    debug!("Error while storing end of game stats: {}", e);
+                }
+            }
+            Err(error) => debug!("Could not fetch end of game stats: {}", error),
+        }
+        RecordStatus::Complete
+    };
+    save_record(&record, status, None).await?;
+    if status == RecordStatus::Complete {
+        index_record(record_id).await;
+    }
+
+    Ok(record)
+}
+
+async fn save_record(
+    record: &Record,
+    status: RecordStatus,
+    reason: Option<&ApiError>,
+) -> Result<String, RecordingError> {
+    let stored_record = StoredRecord {
+        status_reason: reason.map(ApiError::to_string),
+        ..StoredRecord::from_recording(record, status)
+    };
+    let record_id = db::blocking(move || queries::save_record(&stored_record)).await?;
+    record.handle.emit(RecordingEventKind::Saved {
+        status: status.to_string(),
+    });
+
+    Ok(record_id)
+}
+
+/// Mark a stored recording of the game as failed when the spectator API no
+/// longer serves it, so it is not resumed again on the next start.
+async fn fail_stored_record(
+    endpoint: &SpectatorEndpoint,
+    game_id: &str,
+    error: ApiError,
+) -> ApiError {
+    if error.is_transient() {
+        return error;
+    }
+
+    let platform_id = endpoint.platform_id.clone();
+    let game_id = game_id.to_string();
+    let reason = error.to_string();
+    let result = db::blocking(move || {
+        let stored_record = queries::get_platform_record(&platform_id, &game_id)?
+            .filter(|stored_record| stored_record.record_status() == Some(RecordStatus::Recording));
+        if let Some(stored_record) = stored_record {
+            queries::save_record(&StoredRecord {
+                status: RecordStatus::Failed.to_string(),
+                status_reason: Some(reason),
+                ..stored_record
+            })?;
+        }
+
+        Ok(())
+    })
+    .await;
+    if let Err(db_error) = result {
+        debug!("Could not mark the stored record as failed: {}", db_error);
+    }
+
+    error
+}
+
+/// Store the game time covered by each chunk and keyframe, used to seek in
+/// the replay.
+async fn index_record(record_id: String) {
+    match spawn_blocking(move || time_index::index_record(&record_id)).await {
+        Ok(Ok(index)) => debug!("Indexed {} chunks", index.chunks.len()),
+        Ok(Err(error)) => debug!("Could not index record: {}", error),
+        Err(error) => debug!("Could not index record: {}", error),
+    }
 }
 
-async fn record_media_data(record: Arc<Record>) -> Result<Record, RecordingError> {
+/// Keep the chunk durations and keyframe mapping the metadata carries, they
+/// only list the latest chunks so they are lost if not stored as we go.
+async fn save_meta_data_chunks(record_id: &str, record: &Record) {
+    if let Some(metadata) = &record.metadata {
+        save_record_chunks(RecordChunk::from_game_meta_data(record_id, metadata)).await;
+    }
+}
+
+/// Chunk details are only needed to replay at the recorded cadence, losing
+/// some does not stop the recording.
+async fn save_record_chunks(chunks: Vec<RecordChunk>) {
+    if let Err(error) = db::blocking(move || queries::save_record_chunks(chunks)).await {
+        debug!("Could not save record chunks: {}", error);
+    }
+}
+
+async fn record_media_data(record: Arc<Record>, record_id: &str) -> Result<Record, RecordingError> {
     let endpoint = record.endpoint.clone();
     let game_id = record.game_id.clone();
-    let mut tasks = Vec::new();
-    let mut current_chunk_id = 1;
-    let mut current_keyframe_id = 1;
+    let scheduler = DownloadScheduler::new(
+        record.clone(),
+        settings::current().http.max_concurrent_downloads,
+    );
+    let downloads = spawn(scheduler.clone().run());
+    // Start after what is already on disk when resuming a recording
+    let mut current_chunk_id = record.next_missing_game_data_chunk();
+    let mut current_keyframe_id = record.next_missing_keyframe();
+
+    let mut failure = None;
+    let mut poll_failures = 0;
 
-    loop {
-        match endpoints::fetch_last_chunk_info(&endpoint, &game_id).await {
+    while !record.handle.is_cancelled() {
+        // Retries are paced by the request backoff, do not outlive a cancel
+        let chunk_info = tokio::select! {
+            result = endpoints::fetch_last_chunk_info(&endpoint, &game_id) => result,
+            _ = record.handle.cancelled() => break,
+        };
+
+        match chunk_info {
             Ok(chunk_info) => {
+                poll_failures = 0;
+                record.handle.update(|status| {
+                    status.last_chunk_id = chunk_info.chunk_id;
+                    status.end_game_chunk_id = chunk_info.end_game_chunk_id;
+                });
+                save_record_chunks(vec![RecordChunk::from_chunk_info(record_id, &chunk_info)])
+                    .await;
+
                 if chunk_info.chunk_id != current_chunk_id
                     || chunk_info.key_frame_id != current_keyframe_id
                 {
                     debug!("Received first chunk info but there is a gap between chunk_id or keyframe_id try to download previous media data");
-                    let record_clone = record.clone();
-                    let process_previous_media_data_task = spawn(async move {
-                        let _ = process_previous_media_data(
-                            record_clone,
-                            chunk_info.chunk_id,
-                            chunk_info.key_frame_id,
-                        )
-                        .await;
-                    });
-                    tasks.push(process_previous_media_data_task);
+                    scheduler.backfill(chunk_info.chunk_id, chunk_info.key_frame_id);
 
                     current_chunk_id = chunk_info.chunk_id;
                     current_keyframe_id = chunk_info.key_frame_id;
                 }
 
-                let record_clone = record.clone();
-                let process_media_data_task = spawn(async move {
-                    let _ = process_media_data(
-                        record_clone,
-                        chunk_info.chunk_id,
-                        chunk_info.key_frame_id,
-                    )
-                    .await;
-                });
-
-                tasks.push(process_media_data_task);
+                scheduler.push(
+                    MediaItem::GameDataChunk(chunk_info.chunk_id),
+                    Priority::Live,
+                );
+                scheduler.push(MediaItem::KeyFrame(chunk_info.key_frame_id), Priority::Live);
 
                 if chunk_info.chunk_id == chunk_info.end_game_chunk_id {
                     debug!("Received last chunk info");
+                    record.handle.emit(RecordingEventKind::GameEnded {
+                        end_game_chunk_id: chunk_info.end_game_chunk_id,
+                    });
                     break;
                 }
 
@@ -79,102 +242,66 @@ async fn record_media_data(record: Arc<Record>) -> Result<Record, RecordingError
                 let waiting_time = Duration::from_millis(chunk_info.next_available_chunk as u64)
                     + Duration::from_secs(1);
                 debug!("Wait {:?} milliseconds before next iteration", waiting_time);
-                sleep(waiting_time).await;
+                wait_or_cancel(&record.handle, waiting_time).await;
             }
             Err(error) => {
+                record.handle.emit(RecordingEventKind::FetchError {
+                    message: error.to_string(),
+                });
+                poll_failures += 1;
+                if !error.is_transient() || poll_failures >= MAX_POLL_FAILURES {
+                    debug!("Stopping the recording on last chunk info error: {}", error);
+                    failure = Some(error);
+                    break;
+                }
+
+                let waiting_time = match error {
+                    ApiError::RateLimited {
+                        retry_after: Some(retry_after),
+                    } => retry_after,
+                    _ => Duration::from_secs(30),
+                };
                 debug!(
-                    "Record Frames received error {} retry in 10 seconds...",
-                    error
+                    "Last chunk info received error {} retry in {:?}",
+                    error, waiting_time
                 );
-                sleep(Duration::from_secs(10)).await;
-                continue;
+                wait_or_cancel(&record.handle, waiting_time).await;
             }
         }
     }
 
-    debug!("Awaiting for tasks");
-    for task in tasks {
-        let _ = task.await;
-    }
-
-    Arc::try_unwrap(record).map_err(|_| RecordingError::ArcUnwrapError)
-}
-
-async fn process_previous_media_data(
-    record: Arc<Record>,
-    current_chunk_id: u32,
-    current_key_frame_id: u32,
-) -> Result<(), reqwest::Error> {
-    for chunk_id in (1..=current_chunk_id - 1).rev() {
-        let _ = fetch_and_store_game_data_chunk(record.clone(), chunk_id).await;
-    }
-
-    for keyframe_id in (1..=current_key_frame_id - 1).rev() {
-        let _ = fetch_and_store_keyframe(record.clone(), keyframe_id).await;
-    }
-
-    Ok(())
-}
-
-async fn process_media_data(
-    record: Arc<Record>,
-    chunk_id: u32,
-    keyframe_id: u32,
-) -> Result<(), reqwest::Error> {
-    let _ = fetch_and_store_game_data_chunk(record.clone(), chunk_id).await;
-    let _ = fetch_and_store_keyframe(record, keyframe_id).await;
+    // A failed recording only waits for the running downloads
+    let window = if failure.is_some() {
+        Duration::ZERO
+    } else {
+        AVAILABILITY_WINDOW
+    };
+    scheduler.close_after(window);
+    debug!("Awaiting for downloads");
+    let _ = downloads.await;
 
-    Ok(())
-}
-
-async fn fetch_and_store_game_data_chunk(
-    record: Arc<Record>,
-    chunk_id: u32,
-) -> Result<(), reqwest::Error> {
-    // Return if the chunk ID is already in the set
-    if record.has_game_data_chunk(chunk_id) {
-        return Ok(());
+    for (item, failed) in scheduler.failed() {
+        debug!(
+            "Gave up on {} after {} attempts: {}",
+            item, failed.attempts, failed.error
+        );
     }
+    drop(scheduler);
 
-    match endpoints::fetch_game_data_chunk(&record.endpoint, &record.game_id, chunk_id).await {
-        Ok(game_data_chunk) => {
-            debug!("Storing game data chunk id {}", chunk_id);
-            if let Err(e) = record.store_game_data_chunk(chunk_id, game_data_chunk) {
-                debug!("Error while storing chunk: {}", e);
-            } else {
-                record.insert_game_data_chunk(chunk_id);
-            }
-        }
-        Err(error) => {
-            debug!("error {}", error);
-            return Err(error);
+    if let Some(error) = failure {
+        if let Err(save_error) = save_record(&record, RecordStatus::Failed, Some(&error)).await {
+            debug!("Could not save the failed recording: {}", save_error);
         }
+        return Err(error.into());
     }
-    Ok(())
-}
 
-async fn fetch_and_store_keyframe(
-    record: Arc<Record>,
-    keyframe_id: u32,
-) -> Result<(), reqwest::Error> {
-    // Return if the keyframe ID is already in the set
-    if record.has_keyframe(keyframe_id) {
-        return Ok(());
-    }
+    Arc::try_unwrap(record).map_err(|_| RecordingError::ArcUnwrapError)
+}
 
-    match endpoints::fetch_keyframe(&record.endpoint, &record.game_id, keyframe_id).await {
-        Ok(keyframe) => {
-            debug!("Storing keyframe {}", keyframe_id);
-            if let Err(e) = record.store_key_frame(keyframe_id, keyframe) {
-                debug!("Error while storing keyframe: {}", e);
-            } else {
-                record.insert_keyframe(keyframe_id);
-            }
-        }
-        Err(error) => {
-            debug!("error {}", error);
-            return Err(error);
-        }
+/// Sleep for `duration`, returning early if the recording gets cancelled.
+async fn wait_or_cancel(handle: &RecordingHandle, duration: Duration) {
+    tokio::select! {
+        _ = sleep(duration) => {}
+        _ = handle.cancelled() => {}
     }
-    Ok(())
 }
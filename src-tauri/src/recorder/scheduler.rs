@@ -0,0 +1,475 @@
+use super::api::client::RequestKind;
+use super::api::endpoints;
+use super::error::RecordingError;
+use super::models::{Record, RecordingEventKind};
+
+use log::debug;
+use serde::Serialize;
+use tokio::spawn;
+use tokio::sync::{Notify, Semaphore};
+use tokio::time::{sleep_until, Duration, Instant};
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashSet};
+use std::fmt;
+use std::future;
+use std::sync::{Arc, Mutex};
+
+/// Piece of a game to download.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MediaItem {
+    GameDataChunk(u32),
+    KeyFrame(u32),
+}
+
+impl MediaItem {
+    fn id(self) -> u32 {
+        match self {
+            MediaItem::GameDataChunk(id) | MediaItem::KeyFrame(id) => id,
+        }
+    }
+}
+
+impl fmt::Display for MediaItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaItem::GameDataChunk(id) => write!(f, "chunk {}", id),
+            MediaItem::KeyFrame(id) => write!(f, "keyframe {}", id),
+        }
+    }
+}
+
+/// Downloads with a higher priority start first, newest ids first within a
+/// priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// Download that failed and waits for another attempt.
+    Retry,
+    /// Chunk or keyframe released before the recording started.
+    Backfill,
+    /// Latest chunk or keyframe, the spectator API only keeps the recent ones
+    /// around.
+    Live,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct QueuedDownload {
+    priority: Priority,
+    item: MediaItem,
+}
+
+impl Ord for QueuedDownload {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then(self.item.id().cmp(&other.item.id()))
+            .then(self.item.cmp(&other.item))
+    }
+}
+
+impl PartialOrd for QueuedDownload {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Pending downloads, by priority. An item stays known to the queue from the
+/// moment it is pushed until `done` is called, so it is never queued twice.
+#[derive(Default)]
+struct DownloadQueue {
+    ready: BinaryHeap<QueuedDownload>,
+    delayed: Vec<(Instant, QueuedDownload)>,
+    items: HashSet<MediaItem>,
+}
+
+impl DownloadQueue {
+    /// Queue `item`, to start no earlier than `ready_at` when given. Returns
+    /// false when it is already queued or downloading.
+    fn push(&mut self, item: MediaItem, priority: Priority, ready_at: Option<Instant>) -> bool {
+        if !self.items.insert(item) {
+            return false;
+        }
+
+        let download = QueuedDownload { priority, item };
+        match ready_at {
+            Some(ready_at) => self.delayed.push((ready_at, download)),
+            None => self.ready.push(download),
+        }
+        true
+    }
+
+    /// Download to start now, the most urgent ready one.
+    fn pop(&mut self, now: Instant) -> Option<MediaItem> {
+        let (ready, delayed) = self
+            .delayed
+            .drain(..)
+            .partition::<Vec<_>, _>(|(ready_at, _)| *ready_at <= now);
+        self.delayed = delayed;
+        self.ready
+            .extend(ready.into_iter().map(|(_, download)| download));
+
+        self.ready.pop().map(|download| download.item)
+    }
+
+    fn done(&mut self, item: MediaItem) {
+        self.items.remove(&item);
+    }
+
+    /// When the next delayed download becomes ready.
+    fn next_ready_at(&self) -> Option<Instant> {
+        self.delayed.iter().map(|(ready_at, _)| *ready_at).min()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ready.is_empty() && self.delayed.is_empty()
+    }
+
+    fn clear(&mut self) {
+        for download in self.ready.drain() {
+            self.items.remove(&download.item);
+        }
+        for (_, download) in self.delayed.drain(..) {
+            self.items.remove(&download.item);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedDownload {
+    pub attempts: u32,
+    pub error: String,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    queue: DownloadQueue,
+    in_flight: usize,
+    failed: BTreeMap<MediaItem, FailedDownload>,
+    /// Set once the game ended, failed downloads are retried until then.
+    closes_at: Option<Instant>,
+    backfilling: bool,
+}
+
+enum Next {
+    Download(MediaItem),
+    Wait(Option<Instant>),
+    Done,
+}
+
+/// Downloads the chunks and keyframes of a recording, a limited number at a
+/// time, and keeps retrying failed ones while the game is available.
+pub struct DownloadScheduler {
+    record: Arc<Record>,
+    state: Mutex<SchedulerState>,
+    wake: Notify,
+    permits: Arc<Semaphore>,
+}
+
+impl DownloadScheduler {
+    pub fn new(record: Arc<Record>, max_concurrent_downloads: usize) -> Arc<Self> {
+        Arc::new(DownloadScheduler {
+            record,
+            state: Mutex::new(SchedulerState::default()),
+            wake: Notify::new(),
+            permits: Arc::new(Semaphore::new(max_concurrent_downloads.max(1))),
+        })
+    }
+
+    pub fn push(&self, item: MediaItem, priority: Priority) {
+        if self.is_stored(item) {
+            return;
+        }
+
+        if self.state.lock().unwrap().queue.push(item, priority, None) {
+            self.wake.notify_one();
+        }
+    }
+
+    /// Queue every chunk before `chunk_id` and keyframe before `keyframe_id`
+    /// that is not stored yet.
+    pub fn backfill(&self, chunk_id: u32, keyframe_id: u32) {
+        self.record
+            .handle
+            .emit(RecordingEventKind::BackfillStarted {
+                chunk_id,
+                keyframe_id,
+            });
+        self.state.lock().unwrap().backfilling = true;
+
+        for chunk_id in 1..chunk_id {
+            self.push(MediaItem::GameDataChunk(chunk_id), Priority::Backfill);
+        }
+        for keyframe_id in 1..keyframe_id {
+            self.push(MediaItem::KeyFrame(keyframe_id), Priority::Backfill);
+        }
+    }
+
+    /// No more downloads are coming, `run` returns once the queue is empty or
+    /// `window` is over, giving up on what still fails by then.
+    pub fn close_after(&self, window: Duration) {
+        self.state.lock().unwrap().closes_at = Some(Instant::now() + window);
+        self.wake.notify_one();
+    }
+
+    /// Downloads that failed and have not succeeded since.
+    pub fn failed(&self) -> BTreeMap<MediaItem, FailedDownload> {
+        self.state.lock().unwrap().failed.clone()
+    }
+
+    /// Start the queued downloads until closed or cancelled, in which case
+    /// the running ones are aborted.
+    pub async fn run(self: Arc<Self>) {
+        let mut tasks = Vec::new();
+
+        loop {
+            let permit = tokio::select! {
+                permit = self.permits.clone().acquire_owned() => {
+                    permit.expect("download permits are never closed")
+                }
+                _ = self.record.handle.cancelled() => break,
+            };
+
+            match self.next() {
+                Next::Download(item) => {
+                    let scheduler = self.clone();
+                    tasks.push(spawn(async move {
+                        scheduler.download(item).await;
+                        drop(permit);
+                    }));
+                }
+                Next::Wait(ready_at) => {
+                    drop(permit);
+                    let delayed = async {
+                        match ready_at {
+                            Some(ready_at) => sleep_until(ready_at).await,
+                            None => future::pending().await,
+                        }
+                    };
+                    tokio::select! {
+                        _ = self.wake.notified() => {}
+                        _ = delayed => {}
+                        _ = self.record.handle.cancelled() => break,
+                    }
+                }
+                Next::Done => break,
+            }
+        }
+
+        if self.record.handle.is_cancelled() {
+            debug!("Recording cancelled, aborting pending downloads");
+            for task in &tasks {
+                task.abort();
+            }
+        }
+        for task in tasks {
+            let _ = task.await;
+        }
+    }
+
+    fn next(&self) -> Next {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        let closed = state.closes_at.is_some_and(|closes_at| closes_at <= now);
+        if closed {
+            state.queue.clear();
+        }
+
+        match state.queue.pop(now) {
+            Some(item) => {
+                state.in_flight += 1;
+                Next::Download(item)
+            }
+            None if state.closes_at.is_some() && state.queue.is_empty() && state.in_flight == 0 => {
+                Next::Done
+            }
+            None => Next::Wait(if closed {
+                None
+            } else {
+                earliest(state.queue.next_ready_at(), state.closes_at)
+            }),
+        }
+    }
+
+    async fn download(&self, item: MediaItem) {
+        let result = match item {
+            MediaItem::GameDataChunk(chunk_id) => {
+                self.fetch_and_store_game_data_chunk(chunk_id).await
+            }
+            MediaItem::KeyFrame(keyframe_id) => self.fetch_and_store_keyframe(keyframe_id).await,
+        };
+
+        let mut state = self.state.lock().unwrap();
+        state.in_flight -= 1;
+        state.queue.done(item);
+
+        match result {
+            Ok(()) => {
+                state.failed.remove(&item);
+            }
+            Err(error) => {
+                debug!("Could not download {}: {}", item, error);
+                let failed = state.failed.entry(item).or_insert(FailedDownload {
+                    attempts: 0,
+                    error: String::new(),
+                });
+                failed.attempts += 1;
+                failed.error = error.to_string();
+                let attempts = failed.attempts;
+
+                let transient = match &error {
+                    RecordingError::ApiError(error) => error.is_transient(),
+                    _ => true,
+                };
+                if transient {
+                    let retry_at = Instant::now() + retry_delay(item, attempts);
+                    state.queue.push(item, Priority::Retry, Some(retry_at));
+                }
+            }
+        }
+
+        let failed_downloads = state.failed.len();
+        let backfill_finished = state.backfilling && state.queue.is_empty() && state.in_flight == 0;
+        if backfill_finished {
+            state.backfilling = false;
+        }
+        drop(state);
+
+        self.record
+            .handle
+            .update(|status| status.failed_downloads = failed_downloads);
+        if backfill_finished {
+            self.record
+                .handle
+                .emit(RecordingEventKind::BackfillFinished);
+        }
+        self.wake.notify_one();
+    }
+
+    fn is_stored(&self, item: MediaItem) -> bool {
+        match item {
+            MediaItem::GameDataChunk(chunk_id) => self.record.has_game_data_chunk(chunk_id),
+            MediaItem::KeyFrame(keyframe_id) => self.record.has_keyframe(keyframe_id),
+        }
+    }
+
+    async fn fetch_and_store_game_data_chunk(&self, chunk_id: u32) -> Result<(), RecordingError> {
+        let record = &self.record;
+        // Return if the chunk ID is already in the set
+        if record.has_game_data_chunk(chunk_id) {
+            return Ok(());
+        }
+
+        let game_data_chunk =
+            endpoints::fetch_game_data_chunk(&record.endpoint, &record.game_id, chunk_id)
+                .await
+                .inspect_err(|error| {
+                    record.handle.emit(RecordingEventKind::FetchError {
+                        message: error.to_string(),
+                    });
+                })?;
+
+        debug!("Storing game data chunk id {}", chunk_id);
+        record.store_game_data_chunk(chunk_id, game_data_chunk)?;
+        record.insert_game_data_chunk(chunk_id);
+        record.report_progress();
+        record.handle.emit(RecordingEventKind::ChunkStored {
+            chunk_id,
+            game_data_chunks: record.game_data_chunks.lock().unwrap().len(),
+        });
+
+        Ok(())
+    }
+
+    async fn fetch_and_store_keyframe(&self, keyframe_id: u32) -> Result<(), RecordingError> {
+        let record = &self.record;
+        // Return if the keyframe ID is already in the set
+        if record.has_keyframe(keyframe_id) {
+            return Ok(());
+        }
+
+        let keyframe = endpoints::fetch_keyframe(&record.endpoint, &record.game_id, keyframe_id)
+            .await
+            .inspect_err(|error| {
+                record.handle.emit(RecordingEventKind::FetchError {
+                    message: error.to_string(),
+                });
+            })?;
+
+        debug!("Storing keyframe {}", keyframe_id);
+        record.store_key_frame(keyframe_id, keyframe)?;
+        record.insert_keyframe(keyframe_id);
+        record.report_progress();
+        record.handle.emit(RecordingEventKind::KeyframeStored {
+            keyframe_id,
+            keyframes: record.keyframes.lock().unwrap().len(),
+        });
+
+        Ok(())
+    }
+}
+
+/// Wait before queuing a failed download again, on top of the retries of the
+/// request itself.
+fn retry_delay(item: MediaItem, attempts: u32) -> Duration {
+    let kind = match item {
+        MediaItem::GameDataChunk(_) => RequestKind::GameDataChunk,
+        MediaItem::KeyFrame(_) => RequestKind::KeyFrame,
+    };
+
+    kind.retry_policy().delay(attempts)
+}
+
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_queue_priority() {
+        let now = Instant::now();
+        let mut queue = DownloadQueue::default();
+
+        queue.push(MediaItem::GameDataChunk(1), Priority::Backfill, None);
+        queue.push(MediaItem::GameDataChunk(2), Priority::Backfill, None);
+        queue.push(MediaItem::GameDataChunk(3), Priority::Retry, None);
+        queue.push(MediaItem::GameDataChunk(10), Priority::Live, None);
+        queue.push(MediaItem::KeyFrame(5), Priority::Live, None);
+        assert!(!queue.push(MediaItem::GameDataChunk(1), Priority::Live, None));
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.pop(now)).collect();
+        assert_eq!(
+            order,
+            vec![
+                MediaItem::GameDataChunk(10),
+                MediaItem::KeyFrame(5),
+                MediaItem::GameDataChunk(2),
+                MediaItem::GameDataChunk(1),
+                MediaItem::GameDataChunk(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_queue_delayed() {
+        let now = Instant::now();
+        let later = now + Duration::from_secs(5);
+        let mut queue = DownloadQueue::default();
+
+        queue.push(MediaItem::KeyFrame(1), Priority::Retry, Some(later));
+        assert_eq!(queue.pop(now), None);
+        assert_eq!(queue.next_ready_at(), Some(later));
+        assert_eq!(queue.pop(later), Some(MediaItem::KeyFrame(1)));
+
+        // Known to the queue until done
+        assert!(!queue.push(MediaItem::KeyFrame(1), Priority::Live, None));
+        queue.done(MediaItem::KeyFrame(1));
+        assert!(queue.push(MediaItem::KeyFrame(1), Priority::Live, None));
+    }
+}
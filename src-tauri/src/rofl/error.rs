@@ -0,0 +1,37 @@
+use crate::archive::error::ArchiveError;
+use crate::db::DbError;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RoflError {
+    #[error("IO error occurred: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("invalid replay metadata: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("could not read the recorded files: {0}")]
+    Storage(#[from] ArchiveError),
+
+    #[error("could not access the database: {0}")]
+    Database(#[from] DbError),
+
+    #[error("no record for game {0}")]
+    RecordNotFound(String),
+
+    #[error("game {0} has no metadata")]
+    MissingMetadata(String),
+
+    #[error("not a .rofl replay")]
+    InvalidFormat,
+
+    #[error("replay is truncated or corrupt")]
+    Corrupt,
+
+    #[error("invalid {0} for a record")]
+    InvalidId(&'static str),
+
+    #[error("game {0} is already in the library")]
+    AlreadyImported(String),
+}
@@ -0,0 +1,771 @@
+pub mod error;
+
+use crate::archive::is_valid_id;
+use crate::archive::storage::RecordStorage;
+use crate::decoder::time_index;
+use crate::models::record::{Record, RecordStatus};
+use crate::models::record_chunk::RecordChunk;
+use crate::recorder::api::models::{GameKey, GameMetaData, PendingAvailableKeyFrameInfo};
+use crate::server::timeline::Timeline;
+use crate::{queries, settings};
+use error::RoflError;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const MAGIC: &[u8; 6] = b"RIOT\0\0";
+const SIGNATURE_LENGTH: usize = 256;
+/// Magic, signature, this length itself and the six offsets and lengths.
+const HEADER_LENGTH: u16 = 288;
+/// Payload header without its variable length encryption key.
+const PAYLOAD_HEADER_LENGTH: u32 = 34;
+const PAYLOAD_ENTRY_LENGTH: u32 = 17;
+
+/// Lengths and offsets of the sections, from the start of the file. All
+/// integers of a replay are little endian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoflHeader {
+    pub file_length: u32,
+    pub metadata_offset: u32,
+    pub metadata_length: u32,
+    pub payload_header_offset: u32,
+    pub payload_header_length: u32,
+    pub payload_offset: u32,
+}
+
+impl RoflHeader {
+    fn read_from(reader: &mut impl Read) -> Result<Self, RoflError> {
+        let mut magic = [0; 6];
+        reader.read_exact(&mut magic)?;
+        let mut signature = [0; SIGNATURE_LENGTH];
+        reader.read_exact(&mut signature)?;
+        if &magic != MAGIC || read_u16(reader)? != HEADER_LENGTH {
+            return Err(RoflError::InvalidFormat);
+        }
+
+        Ok(RoflHeader {
+            file_length: read_u32(reader)?,
+            metadata_offset: read_u32(reader)?,
+            metadata_length: read_u32(reader)?,
+            payload_header_offset: read_u32(reader)?,
+            payload_header_length: read_u32(reader)?,
+            payload_offset: read_u32(reader)?,
+        })
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> Result<(), io::Error> {
+        writer.write_all(MAGIC)?;
+        // Replays saved by the client are signed by Riot, ours cannot be
+        writer.write_all(&[0; SIGNATURE_LENGTH])?;
+        writer.write_all(&HEADER_LENGTH.to_le_bytes())?;
+
+        for value in [
+            self.file_length,
+            self.metadata_offset,
+            self.metadata_length,
+            self.payload_header_offset,
+            self.payload_header_length,
+            self.payload_offset,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RoflMetadata {
+    /// Length of the game in milliseconds.
+    pub game_length: u64,
+    pub game_version: String,
+    pub last_game_chunk_id: u32,
+    pub last_key_frame_id: u32,
+    /// Player statistics, a JSON array encoded as a string.
+    pub stats_json: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PayloadHeader {
+    pub game_id: u64,
+    /// Length of the game in milliseconds.
+    pub game_length: u32,
+    pub keyframe_count: u32,
+    pub chunk_count: u32,
+    pub end_startup_chunk_id: u32,
+    pub start_game_chunk_id: u32,
+    /// Time between two keyframes in milliseconds.
+    pub keyframe_interval: u32,
+    pub encryption_key: String,
+}
+
+impl PayloadHeader {
+    fn length(&self) -> u32 {
+        PAYLOAD_HEADER_LENGTH + self.encryption_key.len() as u32
+    }
+
+    /// Read a payload header of at most `length` bytes.
+    fn read_from(reader: &mut impl Read, length: u32) -> Result<Self, RoflError> {
+        let game_id = read_u64(reader)?;
+        let game_length = read_u32(reader)?;
+        let keyframe_count = read_u32(reader)?;
+        let chunk_count = read_u32(reader)?;
+        let end_startup_chunk_id = read_u32(reader)?;
+        let start_game_chunk_id = read_u32(reader)?;
+        let keyframe_interval = read_u32(reader)?;
+        let encryption_key_length = read_u16(reader)?;
+        if PAYLOAD_HEADER_LENGTH + encryption_key_length as u32 > length {
+            return Err(RoflError::Corrupt);
+        }
+        let mut encryption_key = vec![0; encryption_key_length as usize];
+        reader.read_exact(&mut encryption_key)?;
+
+        Ok(PayloadHeader {
+            game_id,
+            game_length,
+            keyframe_count,
+            chunk_count,
+            end_startup_chunk_id,
+            start_game_chunk_id,
+            keyframe_interval,
+            encryption_key: String::from_utf8(encryption_key)
+                .map_err(|_| RoflError::InvalidFormat)?,
+        })
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> Result<(), io::Error> {
+        writer.write_all(&self.game_id.to_le_bytes())?;
+
+        for value in [
+            self.game_length,
+            self.keyframe_count,
+            self.chunk_count,
+            self.end_startup_chunk_id,
+            self.start_game_chunk_id,
+            self.keyframe_interval,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+
+        writer.write_all(&(self.encryption_key.len() as u16).to_le_bytes())?;
+        writer.write_all(self.encryption_key.as_bytes())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PayloadEntryKind {
+    Chunk = 1,
+    KeyFrame = 2,
+}
+
+/// Entry of the list following the payload header, the data of every entry
+/// comes after the list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PayloadEntry {
+    pub id: u32,
+    pub kind: PayloadEntryKind,
+    pub length: u32,
+    /// Chunk to play after a keyframe, 0 for chunks.
+    pub next_chunk_id: u32,
+    /// Offset of the data from the end of the entry list.
+    pub offset: u32,
+}
+
+impl PayloadEntry {
+    fn read_from(reader: &mut impl Read) -> Result<Self, RoflError> {
+        let id = read_u32(reader)?;
+        let mut kind = [0; 1];
+        reader.read_exact(&mut kind)?;
+        let kind = match kind[0] {
+            1 => PayloadEntryKind::Chunk,
+            2 => PayloadEntryKind::KeyFrame,
+            _ => return Err(RoflError::InvalidFormat),
+        };
+
+        Ok(PayloadEntry {
+            id,
+            kind,
+            length: read_u32(reader)?,
+            next_chunk_id: read_u32(reader)?,
+            offset: read_u32(reader)?,
+        })
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> Result<(), io::Error> {
+        writer.write_all(&self.id.to_le_bytes())?;
+        writer.write_all(&[self.kind as u8])?;
+        writer.write_all(&self.length.to_le_bytes())?;
+        writer.write_all(&self.next_chunk_id.to_le_bytes())?;
+        writer.write_all(&self.offset.to_le_bytes())
+    }
+}
+
+fn read_u16(reader: &mut impl Read) -> Result<u16, io::Error> {
+    let mut bytes = [0; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, io::Error> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, io::Error> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// A replay read up to its payload entries, the data of each entry is read on
+/// demand.
+#[derive(Debug)]
+pub struct Rofl {
+    pub header: RoflHeader,
+    pub metadata: RoflMetadata,
+    pub payload_header: PayloadHeader,
+    pub entries: Vec<PayloadEntry>,
+}
+
+impl Rofl {
+    /// Every length and offset of the file is checked against its actual
+    /// length before allocating or reading anything.
+    pub fn read_from(reader: &mut (impl Read + Seek)) -> Result<Self, RoflError> {
+        let file_length = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        let header = RoflHeader::read_from(reader)?;
+        let fits = |offset: u64, length: u64| offset + length <= file_length;
+
+        if !fits(header.metadata_offset as u64, header.metadata_length as u64)
+            || !fits(
+                header.payload_header_offset as u64,
+                header.payload_header_length as u64,
+            )
+        {
+            return Err(RoflError::Corrupt);
+        }
+
+        reader.seek(SeekFrom::Start(header.metadata_offset as u64))?;
+        let mut metadata = vec![0; header.metadata_length as usize];
+        reader.read_exact(&mut metadata)?;
+
+        reader.seek(SeekFrom::Start(header.payload_header_offset as u64))?;
+        let payload_header = PayloadHeader::read_from(reader, header.payload_header_length)?;
+
+        let entry_count = payload_header
+            .chunk_count
+            .checked_add(payload_header.keyframe_count)
+            .ok_or(RoflError::Corrupt)?;
+        let data_start =
+            header.payload_offset as u64 + entry_count as u64 * PAYLOAD_ENTRY_LENGTH as u64;
+        if data_start > file_length {
+            return Err(RoflError::Corrupt);
+        }
+
+        reader.seek(SeekFrom::Start(header.payload_offset as u64))?;
+        let entries: Vec<PayloadEntry> = (0..entry_count)
+            .map(|_| PayloadEntry::read_from(reader))
+            .collect::<Result<_, _>>()?;
+        if !entries
+            .iter()
+            .all(|entry| fits(data_start + entry.offset as u64, entry.length as u64))
+        {
+            return Err(RoflError::Corrupt);
+        }
+
+        Ok(Rofl {
+            header,
+            metadata: serde_json::from_slice(&metadata)?,
+            payload_header,
+            entries,
+        })
+    }
+
+    pub fn read_entry(
+        &self,
+        reader: &mut (impl Read + Seek),
+        entry: &PayloadEntry,
+    ) -> Result<Vec<u8>, RoflError> {
+        let data_offset = self.header.payload_offset as u64
+            + self.entries.len() as u64 * PAYLOAD_ENTRY_LENGTH as u64
+            + entry.offset as u64;
+        reader.seek(SeekFrom::Start(data_offset))?;
+
+        let mut data = vec![0; entry.length as usize];
+        reader.read_exact(&mut data)?;
+
+        Ok(data)
+    }
+}
+
+/// Payload entries and their data, in order.
+#[derive(Default)]
+struct Payload {
+    entries: Vec<PayloadEntry>,
+    data: Vec<Vec<u8>>,
+    length: u32,
+}
+
+impl Payload {
+    fn push(&mut self, id: u32, kind: PayloadEntryKind, next_chunk_id: u32, data: Vec<u8>) {
+        let length = data.len() as u32;
+
+        self.entries.push(PayloadEntry {
+            id,
+            kind,
+            length,
+            next_chunk_id,
+            offset: self.length,
+        });
+        self.data.push(data);
+        self.length += length;
+    }
+
+    fn count(&self, kind: PayloadEntryKind) -> u32 {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind == kind)
+            .count() as u32
+    }
+}
+
+/// Write a game of the library as a `.rofl` replay at `path`.
+pub fn export_record(game_id: &str, path: &Path) -> Result<(), RoflError> {
+    let record = queries::get_record(game_id.to_string())?
+        .ok_or_else(|| RoflError::RecordNotFound(game_id.to_string()))?;
+    let chunks = queries::get_record_chunks(&record.id)?;
+    let storage = RecordStorage::open(&record)?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_rofl(&record, chunks, &storage, &mut writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn write_rofl(
+    record: &Record,
+    chunks: Vec<RecordChunk>,
+    storage: &RecordStorage,
+    writer: &mut impl Write,
+) -> Result<(), RoflError> {
+    let metadata = record
+        .game_meta_data()
+        .ok_or_else(|| RoflError::MissingMetadata(record.game_id.clone()))?;
+    let timeline = Timeline::new(&metadata, chunks);
+    let chunk_ids: BTreeSet<u32> = record.game_data_chunk_ids().into_iter().collect();
+    let keyframe_ids: BTreeSet<u32> = record.keyframe_ids().into_iter().collect();
+
+    let mut payload = Payload::default();
+    for &chunk_id in &chunk_ids {
+        if let Some(data) = storage.game_data_chunk(chunk_id)? {
+            payload.push(chunk_id, PayloadEntryKind::Chunk, 0, data);
+        }
+    }
+    for &keyframe_id in &keyframe_ids {
+        if let Some(data) = storage.keyframe(keyframe_id)? {
+            let next_chunk_id = timeline.next_chunk_for_key_frame(keyframe_id);
+            payload.push(keyframe_id, PayloadEntryKind::KeyFrame, next_chunk_id, data);
+        }
+    }
+
+    let game_length = if metadata.game_length > 0 {
+        metadata.game_length
+    } else {
+        chunk_ids
+            .iter()
+            .map(|&chunk_id| timeline.duration(chunk_id))
+            .sum()
+    };
+
+    // The spectator API end of game stats are not in the client format
+    let rofl_metadata = serde_json::to_vec(&RoflMetadata {
+        game_length: game_length as u64,
+        game_version: record.version.clone(),
+        last_game_chunk_id: chunk_ids.last().copied().unwrap_or_default(),
+        last_key_frame_id: keyframe_ids.last().copied().unwrap_or_default(),
+        stats_json: "[]".to_string(),
+    })?;
+    let payload_header = PayloadHeader {
+        game_id: metadata.game_key.game_id,
+        game_length,
+        keyframe_count: payload.count(PayloadEntryKind::KeyFrame),
+        chunk_count: payload.count(PayloadEntryKind::Chunk),
+        end_startup_chunk_id: metadata.end_startup_chunk_id,
+        start_game_chunk_id: metadata.start_game_chunk_id,
+        // Keyframes are taken every two chunks
+        keyframe_interval: metadata.chunk_time_interval * 2,
+        encryption_key: record.encryption_key.clone(),
+    };
+
+    let metadata_offset = HEADER_LENGTH as u32;
+    let payload_header_offset = metadata_offset + rofl_metadata.len() as u32;
+    let payload_offset = payload_header_offset + payload_header.length();
+    let header = RoflHeader {
+        file_length: payload_offset
+            + payload.entries.len() as u32 * PAYLOAD_ENTRY_LENGTH
+            + payload.length,
+        metadata_offset,
+        metadata_length: rofl_metadata.len() as u32,
+        payload_header_offset,
+        payload_header_length: payload_header.length(),
+        payload_offset,
+    };
+
+    header.write_to(writer)?;
+    writer.write_all(&rofl_metadata)?;
+    payload_header.write_to(writer)?;
+    for entry in &payload.entries {
+        entry.write_to(writer)?;
+    }
+    for data in &payload.data {
+        writer.write_all(data)?;
+    }
+
+    Ok(())
+}
+
+/// Add a `.rofl` replay to the library, its chunks and keyframes are written
+/// to `storage_path` like a recorded game. Replays do not tell their platform,
+/// it is taken from `platform_id`, then from file names like
+/// `KR-6654667050.rofl`, then from the default region.
+pub fn import_record(
+    path: &Path,
+    storage_path: &Path,
+    platform_id: Option<String>,
+) -> Result<Record, RoflError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let rofl = Rofl::read_from(&mut reader)?;
+    let game_id = rofl.payload_header.game_id.to_string();
+    let platform_id = platform_id
+        .or_else(|| platform_id_from_file_name(path, &game_id))
+        .unwrap_or_else(|| settings::current().default_region.to_endpoint().platform_id);
+
+    // Both ids name the record directory
+    if !is_valid_id(&platform_id) {
+        return Err(RoflError::InvalidId("platform id"));
+    }
+    if !is_valid_id(&game_id) {
+        return Err(RoflError::InvalidId("game id"));
+    }
+
+    if queries::get_record(game_id.clone())?.is_some() {
+        return Err(RoflError::AlreadyImported(game_id));
+    }
+
+    let record_path = storage_path.join(format!("{}_{}", platform_id, game_id));
+    let game_data_chunks_path = record_path.join("game_data_chunks");
+    let keyframes_path = record_path.join("keyframes");
+    fs::create_dir_all(&game_data_chunks_path)?;
+    fs::create_dir_all(&keyframes_path)?;
+
+    let mut game_data_chunks = BTreeSet::new();
+    let mut keyframes = BTreeMap::new();
+    for entry in &rofl.entries {
+        let data = rofl.read_entry(&mut reader, entry)?;
+        match entry.kind {
+            PayloadEntryKind::Chunk => {
+                fs::write(game_data_chunks_path.join(entry.id.to_string()), data)?;
+                game_data_chunks.insert(entry.id);
+            }
+            PayloadEntryKind::KeyFrame => {
+                fs::write(keyframes_path.join(entry.id.to_string()), data)?;
+                keyframes.insert(entry.id, entry.next_chunk_id);
+            }
+        }
+    }
+
+    let metadata = game_meta_data(&rofl, &platform_id, &game_data_chunks, &keyframes);
+    let record = Record {
+        id: Uuid::new_v4().to_string(),
+        version: rofl.metadata.game_version.clone(),
+        base_url: String::new(),
+        platform_id,
+        game_id,
+        encryption_key: rofl.payload_header.encryption_key.clone(),
+        metadata: serde_json::to_string(&metadata)?,
+        keyframes: serde_json::to_string(&keyframes.keys().collect::<Vec<_>>())?,
+        game_data_chunks: serde_json::to_string(&game_data_chunks)?,
+        storage_path: record_path.display().to_string(),
+        created_at: chrono::Utc::now().naive_utc(),
+        status: RecordStatus::Complete.to_string(),
+        status_reason: None,
+    };
+
+    let record_id = queries::save_record(&record)?;
+    queries::save_record_chunks(
+        metadata
+            .pending_available_key_frame_info
+            .iter()
+            .map(|info| RecordChunk {
+                key_frame_id: Some(info.key_frame_id as i32),
+                next_chunk_id: Some(info.next_chunk_id as i32),
+                ..RecordChunk::new(record_id.clone(), info.next_chunk_id)
+            })
+            .collect(),
+    )?;
+    if let Err(error) = time_index::index_record(&record_id) {
+        debug!("Could not index imported record: {}", error);
+    }
+
+    Ok(Record {
+        id: record_id,
+        ..record
+    })
+}
+
+fn platform_id_from_file_name(path: &Path, game_id: &str) -> Option<String> {
+    let file_stem = path.file_stem()?.to_str()?;
+    let (platform_id, file_game_id) = file_stem.split_once('-')?;
+
+    (file_game_id == game_id && !platform_id.is_empty()).then(|| platform_id.to_uppercase())
+}
+
+/// Spectator metadata of a finished game, as the replay server needs it.
+fn game_meta_data(
+    rofl: &Rofl,
+    platform_id: &str,
+    game_data_chunks: &BTreeSet<u32>,
+    keyframes: &BTreeMap<u32, u32>,
+) -> GameMetaData {
+    let payload_header = &rofl.payload_header;
+    let last_chunk_id = game_data_chunks.last().copied().unwrap_or_default();
+    let last_key_frame_id = keyframes.keys().last().copied().unwrap_or_default();
+
+    GameMetaData {
+        game_key: GameKey {
+            game_id: payload_header.game_id,
+            platform_id: platform_id.to_string(),
+        },
+        game_server_address: String::new(),
+        port: 0,
+        encryption_key: payload_header.encryption_key.clone(),
+        // Keyframes are taken every two chunks
+        chunk_time_interval: payload_header.keyframe_interval / 2,
+        start_time: String::new(),
+        game_ended: true,
+        last_chunk_id,
+        last_key_frame_id,
+        end_startup_chunk_id: payload_header.end_startup_chunk_id,
+        delay_time: 0,
+        pending_available_chunk_info: Vec::new(),
+        pending_available_key_frame_info: keyframes
+            .iter()
+            .map(
+                |(&key_frame_id, &next_chunk_id)| PendingAvailableKeyFrameInfo {
+                    key_frame_id,
+                    received_time: String::new(),
+                    next_chunk_id,
+                },
+            )
+            .collect(),
+        key_frame_time_interval: payload_header.keyframe_interval as u64,
+        decoded_encryption_key: String::new(),
+        start_game_chunk_id: payload_header.start_game_chunk_id,
+        game_length: payload_header.game_length,
+        client_added_lag: 0,
+        client_back_fetching_enabled: false,
+        client_back_fetching_freq: 1000,
+        interest_score: 0,
+        featured_game: false,
+        create_time: String::new(),
+        end_game_chunk_id: last_chunk_id as i32,
+        end_game_key_frame_id: last_key_frame_id as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{stored_record, test_dir};
+
+    fn u32_at(bytes: &[u8], offset: u32) -> u32 {
+        let offset = offset as usize;
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_read_written_rofl() {
+        let dir = test_dir("rofl-read");
+        let record = stored_record(&dir.join("KR_6654667050"));
+        let storage = RecordStorage::open(&record).unwrap();
+        let mut bytes = Vec::new();
+        write_rofl(&record, Vec::new(), &storage, &mut bytes).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        let mut reader = io::Cursor::new(bytes);
+        let rofl = Rofl::read_from(&mut reader).unwrap();
+
+        assert_eq!(rofl.metadata.game_version, "13.16.526.3432");
+        assert_eq!(rofl.payload_header.game_id, 6654667050);
+        assert_eq!(rofl.payload_header.encryption_key, record.encryption_key);
+        assert_eq!(rofl.payload_header.start_game_chunk_id, 2);
+        assert_eq!(rofl.entries.len(), 3);
+        assert_eq!(rofl.entries[2].kind, PayloadEntryKind::KeyFrame);
+        assert_eq!(rofl.entries[2].next_chunk_id, 2);
+        assert_eq!(
+            rofl.read_entry(&mut reader, &rofl.entries[1]).unwrap(),
+            b"chunk two"
+        );
+        assert_eq!(
+            rofl.read_entry(&mut reader, &rofl.entries[2]).unwrap(),
+            b"keyframe"
+        );
+
+        let metadata = game_meta_data(
+            &rofl,
+            "KR",
+            &BTreeSet::from([1, 2]),
+            &BTreeMap::from([(1, 2)]),
+        );
+        assert_eq!(metadata.chunk_time_interval, 30000);
+        assert_eq!(metadata.end_game_chunk_id, 2);
+        assert_eq!(
+            metadata.pending_available_key_frame_info[0].next_chunk_id,
+            2
+        );
+    }
+
+    #[test]
+    fn test_read_rejects_other_files() {
+        let mut reader = io::Cursor::new(vec![0; 512]);
+
+        assert!(matches!(
+            Rofl::read_from(&mut reader),
+            Err(RoflError::InvalidFormat)
+        ));
+    }
+
+    #[test]
+    fn test_read_rejects_out_of_bounds_lengths() {
+        let dir = test_dir("rofl-corrupt");
+        let record = stored_record(&dir.join("KR_6654667050"));
+        let storage = RecordStorage::open(&record).unwrap();
+        let mut bytes = Vec::new();
+        write_rofl(&record, Vec::new(), &storage, &mut bytes).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        let payload_header_offset = u32_at(&bytes, 276);
+
+        // Metadata length past the end of the file
+        let mut corrupt = bytes.clone();
+        corrupt[272..276].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            Rofl::read_from(&mut io::Cursor::new(corrupt)),
+            Err(RoflError::Corrupt)
+        ));
+
+        // Entry counts overflowing once added
+        let mut corrupt = bytes.clone();
+        let counts = payload_header_offset as usize + 12;
+        corrupt[counts..counts + 8].copy_from_slice(&[0xff; 8]);
+        assert!(matches!(
+            Rofl::read_from(&mut io::Cursor::new(corrupt)),
+            Err(RoflError::Corrupt)
+        ));
+
+        // Entry data past the end of the file
+        let truncated = bytes[..bytes.len() - 1].to_vec();
+        assert!(matches!(
+            Rofl::read_from(&mut io::Cursor::new(truncated)),
+            Err(RoflError::Corrupt)
+        ));
+    }
+
+    #[test]
+    fn test_import_rejects_paths_in_ids() {
+        let dir = test_dir("rofl-import-traversal");
+        let record = stored_record(&dir.join("KR_6654667050"));
+        let storage = RecordStorage::open(&record).unwrap();
+        let path = dir.join("game.rofl");
+        let mut writer = BufWriter::new(File::create(&path).unwrap());
+        write_rofl(&record, Vec::new(), &storage, &mut writer).unwrap();
+        drop(writer);
+
+        assert!(matches!(
+            import_record(&path, &dir.join("storage"), Some("../..".to_string())),
+            Err(RoflError::InvalidId("platform id"))
+        ));
+        assert!(!dir.join("storage").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_platform_id_from_file_name() {
+        let game_id = "6654667050";
+
+        assert_eq!(
+            platform_id_from_file_name(Path::new("/replays/KR-6654667050.rofl"), game_id),
+            Some("KR".to_string())
+        );
+        assert_eq!(
+            platform_id_from_file_name(Path::new("euw1-6654667050.rofl"), game_id),
+            Some("EUW1".to_string())
+        );
+        assert_eq!(
+            platform_id_from_file_name(Path::new("replay.rofl"), game_id),
+            None
+        );
+    }
+
+    #[test]
+    fn test_write_rofl_layout() {
+        let dir = test_dir("rofl-layout");
+        let record = stored_record(&dir.join("KR_6654667050"));
+        let storage = RecordStorage::open(&record).unwrap();
+        let mut bytes = Vec::new();
+
+        write_rofl(&record, Vec::new(), &storage, &mut bytes).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(&bytes[..6], MAGIC);
+        assert_eq!(u16::from_le_bytes([bytes[262], bytes[263]]), HEADER_LENGTH);
+        assert_eq!(u32_at(&bytes, 264) as usize, bytes.len());
+
+        let metadata_offset = u32_at(&bytes, 268);
+        let metadata_length = u32_at(&bytes, 272);
+        let metadata: RoflMetadata = serde_json::from_slice(
+            &bytes[metadata_offset as usize..(metadata_offset + metadata_length) as usize],
+        )
+        .unwrap();
+        assert_eq!(metadata.game_version, "13.16.526.3432");
+        assert_eq!(metadata.last_game_chunk_id, 2);
+        assert_eq!(metadata.last_key_frame_id, 1);
+        assert_eq!(metadata.game_length, 60000);
+
+        let payload_header_offset = u32_at(&bytes, 276);
+        assert_eq!(payload_header_offset, metadata_offset + metadata_length);
+        let start = payload_header_offset as usize;
+        assert_eq!(
+            u64::from_le_bytes(bytes[start..start + 8].try_into().unwrap()),
+            6654667050
+        );
+        // Keyframe then chunk counts
+        assert_eq!(u32_at(&bytes, payload_header_offset + 12), 1);
+        assert_eq!(u32_at(&bytes, payload_header_offset + 16), 2);
+        assert_eq!(u32_at(&bytes, 280), PAYLOAD_HEADER_LENGTH + 32);
+
+        // Second chunk, then the keyframe pointing to it
+        let payload_offset = u32_at(&bytes, 284);
+        assert_eq!(payload_offset, payload_header_offset + u32_at(&bytes, 280));
+        let entry = payload_offset + PAYLOAD_ENTRY_LENGTH;
+        assert_eq!(u32_at(&bytes, entry), 2);
+        assert_eq!(bytes[entry as usize + 4], PayloadEntryKind::Chunk as u8);
+        let data_start = payload_offset + 3 * PAYLOAD_ENTRY_LENGTH + u32_at(&bytes, entry + 13);
+        assert_eq!(
+            &bytes[data_start as usize..(data_start + u32_at(&bytes, entry + 5)) as usize],
+            b"chunk two"
+        );
+        let entry = payload_offset + 2 * PAYLOAD_ENTRY_LENGTH;
+        assert_eq!(bytes[entry as usize + 4], PayloadEntryKind::KeyFrame as u8);
+        assert_eq!(u32_at(&bytes, entry + 9), 2);
+    }
+}
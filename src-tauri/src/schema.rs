@@ -1,5 +1,26 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    record_chunks (record_id, chunk_id) {
+        record_id -> Text,
+        chunk_id -> Integer,
+        duration -> Nullable<Integer>,
+        received_time -> Nullable<Text>,
+        key_frame_id -> Nullable<Integer>,
+        next_chunk_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    record_time_ranges (record_id, kind, media_id) {
+        record_id -> Text,
+        kind -> Text,
+        media_id -> Integer,
+        start_time -> Float,
+        end_time -> Float,
+    }
+}
+
 diesel::table! {
     records (id) {
         id -> Text,
@@ -13,5 +34,12 @@ diesel::table! {
         game_data_chunks -> Text,
         storage_path -> Text,
         created_at -> Timestamp,
+        status -> Text,
+        status_reason -> Nullable<Text>,
     }
 }
+
+diesel::joinable!(record_chunks -> records (record_id));
+diesel::joinable!(record_time_ranges -> records (record_id));
+
+diesel::allow_tables_to_appear_in_same_query!(record_chunks, record_time_ranges, records,);
@@ -0,0 +1,391 @@
+use actix_web::dev::Payload;
+use actix_web::http::header;
+use actix_web::{delete, error, get, post, web, FromRequest, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{self, DbError};
+use crate::decoder::time_index::{TimeHole, TimeIndex};
+use crate::queries;
+use crate::recorder::api::models::{Region, SpectatorEndpoint};
+use crate::recorder::error::RecordingError;
+use crate::recorder::featured::AutoRecordFilter;
+use crate::recorder::manager::RecorderManager;
+use crate::settings::{self, Settings};
+use crate::verify::{self, error::VerifyError};
+
+use std::future::{ready, Ready};
+
+/// Request guard of the control API: when the settings define an `api_token`,
+/// requests must send it as `Authorization: Bearer <token>`. Without one only
+/// requests to a loopback `server_host` without an `Origin` header are let
+/// through, browsers send it so web pages cannot drive the API.
+pub struct Authorized;
+
+impl FromRequest for Authorized {
+    type Error = error::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        ready(if is_authorized(req, &settings::current()) {
+            Ok(Authorized)
+        } else {
+            Err(error::ErrorUnauthorized("missing or invalid API token"))
+        })
+    }
+}
+
+fn is_authorized(req: &HttpRequest, settings: &Settings) -> bool {
+    match &settings.api_token {
+        Some(token) => {
+            req.headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                == Some(token.as_str())
+        }
+        None => settings.is_loopback_server() && !req.headers().contains_key(header::ORIGIN),
+    }
+}
+
+/// Game to record, either from a Riot region or from a custom endpoint given
+/// with `base_url` and `platform_id`.
+#[derive(Debug, Deserialize)]
+pub struct RecordRequest {
+    pub region: Option<Region>,
+    pub base_url: Option<String>,
+    pub platform_id: Option<String>,
+    pub game_id: String,
+    pub encryption_key: String,
+}
+
+impl RecordRequest {
+    fn endpoint(&self) -> Result<SpectatorEndpoint, String> {
+        match (&self.base_url, &self.platform_id) {
+            (Some(base_url), Some(platform_id)) => Ok(SpectatorEndpoint::new(
+                base_url.clone(),
+                platform_id.clone(),
+            )),
+            (None, None) => Ok(self
+                .region
+                .unwrap_or(settings::current().default_region)
+                .to_endpoint()),
+            _ => Err("base_url and platform_id must be given together".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AutoRecordRequest {
+    pub region: Region,
+    #[serde(default)]
+    pub filter: AutoRecordFilter,
+}
+
+/// Game time in seconds to start playback at.
+#[derive(Debug, Deserialize)]
+pub struct SeekQuery {
+    pub time: f32,
+}
+
+#[derive(Serialize)]
+struct TimeIndexResponse {
+    #[serde(flatten)]
+    index: TimeIndex,
+    holes: Vec<TimeHole>,
+}
+
+/// Keyframe to load and chunk to play to start playback at a game time.
+#[derive(Serialize)]
+struct SeekResponse {
+    chunk_id: u32,
+    key_frame_id: Option<u32>,
+}
+
+#[get("/recordings")]
+async fn list_recordings(_: Authorized, manager: web::Data<RecorderManager>) -> HttpResponse {
+    HttpResponse::Ok().json(manager.list())
+}
+
+#[post("/recordings")]
+async fn start_recording(
+    _: Authorized,
+    manager: web::Data<RecorderManager>,
+    request: web::Json<RecordRequest>,
+) -> HttpResponse {
+    let request = request.into_inner();
+    let endpoint = match request.endpoint() {
+        Ok(endpoint) => endpoint,
+        Err(message) => return HttpResponse::BadRequest().body(message),
+    };
+    let storage_path = settings::current().storage_path;
+
+    match manager.into_inner().start(
+        endpoint,
+        request.game_id,
+        request.encryption_key,
+        storage_path,
+    ) {
+        Ok(status) => HttpResponse::Created().json(status),
+        Err(error @ RecordingError::AlreadyRecording(_)) => {
+            HttpResponse::Conflict().body(error.to_string())
+        }
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+    }
+}
+
+#[get("/recordings/{platform_id}/{game_id}")]
+async fn get_recording(
+    _: Authorized,
+    manager: web::Data<RecorderManager>,
+    path_info: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (platform_id, game_id) = path_info.into_inner();
+
+    match manager.status(&platform_id, &game_id) {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+#[delete("/recordings/{platform_id}/{game_id}")]
+async fn cancel_recording(
+    _: Authorized,
+    manager: web::Data<RecorderManager>,
+    path_info: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (platform_id, game_id) = path_info.into_inner();
+
+    if manager.cancel(&platform_id, &game_id) {
+        HttpResponse::Accepted().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+#[get("/auto-records")]
+async fn list_auto_records(_: Authorized, manager: web::Data<RecorderManager>) -> HttpResponse {
+    HttpResponse::Ok().json(manager.list_auto_records())
+}
+
+#[post("/auto-records")]
+async fn start_auto_record(
+    _: Authorized,
+    manager: web::Data<RecorderManager>,
+    request: web::Json<AutoRecordRequest>,
+) -> HttpResponse {
+    let request = request.into_inner();
+    let storage_path = settings::current().storage_path;
+
+    manager.into_inner().start_auto_record(
+        request.region.to_endpoint(),
+        request.filter,
+        storage_path,
+    );
+
+    HttpResponse::Accepted().finish()
+}
+
+#[delete("/auto-records/{region}")]
+async fn stop_auto_record(
+    _: Authorized,
+    manager: web::Data<RecorderManager>,
+    region: web::Path<Region>,
+) -> HttpResponse {
+    if manager.stop_auto_record(&region.to_endpoint().platform_id) {
+        HttpResponse::Accepted().finish()
+    } else {
+        HttpResponse::NotFound().finish()
+    }
+}
+
+#[get("/records")]
+async fn list_records(_: Authorized) -> HttpResponse {
+    match db::blocking(queries::get_records).await {
+        Ok(records) => HttpResponse::Ok().json(records),
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+    }
+}
+
+#[get("/records/{game_id}")]
+async fn get_record(_: Authorized, game_id: web::Path<String>) -> HttpResponse {
+    match db::blocking(move || queries::get_record(game_id.into_inner())).await {
+        Ok(Some(record)) => HttpResponse::Ok().json(record),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+    }
+}
+
+/// Time index of a recorded game, `None` when there is no such game.
+async fn load_time_index(game_id: String) -> Result<Option<TimeIndex>, DbError> {
+    db::blocking(move || match queries::get_record(game_id)? {
+        Some(record) => TimeIndex::load(&record.id).map(Some),
+        None => Ok(None),
+    })
+    .await
+}
+
+#[get("/records/{game_id}/time-index")]
+async fn get_time_index(_: Authorized, game_id: web::Path<String>) -> HttpResponse {
+    match load_time_index(game_id.into_inner()).await {
+        Ok(Some(index)) => {
+            let holes = index.holes();
+            HttpResponse::Ok().json(TimeIndexResponse { index, holes })
+        }
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+    }
+}
+
+#[get("/records/{game_id}/seek")]
+async fn seek(
+    _: Authorized,
+    game_id: web::Path<String>,
+    query: web::Query<SeekQuery>,
+) -> HttpResponse {
+    let index = match load_time_index(game_id.into_inner()).await {
+        Ok(Some(index)) => index,
+        Ok(None) => return HttpResponse::NotFound().finish(),
+        Err(error) => return HttpResponse::InternalServerError().body(error.to_string()),
+    };
+
+    match index.chunk_at(query.time) {
+        Some(chunk_id) => HttpResponse::Ok().json(SeekResponse {
+            chunk_id,
+            key_frame_id: index.key_frame_before(query.time),
+        }),
+        None => HttpResponse::NotFound().body("no indexed chunk at this time"),
+    }
+}
+
+#[get("/records/{platform_id}/{game_id}/verify")]
+async fn verify_record(_: Authorized, path_info: web::Path<(String, String)>) -> HttpResponse {
+    let (platform_id, game_id) = path_info.into_inner();
+
+    // Every stored file is read and decoded
+    match web::block(move || verify::verify_record(&platform_id, &game_id)).await {
+        Ok(Ok(report)) => HttpResponse::Ok().json(report),
+        Ok(Err(error)) => verify_error_response(error),
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+    }
+}
+
+#[post("/records/{platform_id}/{game_id}/repair")]
+async fn repair_record(_: Authorized, path_info: web::Path<(String, String)>) -> HttpResponse {
+    let (platform_id, game_id) = path_info.into_inner();
+
+    match verify::repair_record(&platform_id, &game_id).await {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(error) => verify_error_response(error),
+    }
+}
+
+fn verify_error_response(error: VerifyError) -> HttpResponse {
+    match error {
+        VerifyError::RecordNotFound(_) => HttpResponse::NotFound().body(error.to_string()),
+        VerifyError::StillRecording(_) | VerifyError::Packed(_) => {
+            HttpResponse::Conflict().body(error.to_string())
+        }
+        _ => HttpResponse::InternalServerError().body(error.to_string()),
+    }
+}
+
+/// Routes of the `/api` scope, they need the `RecorderManager` as app data.
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(list_recordings)
+        .service(start_recording)
+        .service(get_recording)
+        .service(cancel_recording)
+        .service(list_auto_records)
+        .service(start_auto_record)
+        .service(stop_auto_record)
+        .service(list_records)
+        .service(get_record)
+        .service(get_time_index)
+        .service(seek)
+        .service(verify_record)
+        .service(repair_record);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{call_and_read_body_json, call_service, init_service, TestRequest};
+    use actix_web::App;
+    use std::sync::Arc;
+
+    fn record_request(base_url: Option<&str>, platform_id: Option<&str>) -> RecordRequest {
+        RecordRequest {
+            region: Some(Region::KR),
+            base_url: base_url.map(str::to_string),
+            platform_id: platform_id.map(str::to_string),
+            game_id: "6654667050".to_string(),
+            encryption_key: "key".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_record_request_endpoint() {
+        let endpoint = record_request(None, None).endpoint().unwrap();
+        assert_eq!(endpoint.platform_id, "KR");
+
+        let endpoint = record_request(Some("http://localhost:8080"), Some("NA1"))
+            .endpoint()
+            .unwrap();
+        assert_eq!(endpoint.platform_id, "NA1");
+        assert_eq!(endpoint.base_url, "http://localhost:8080");
+
+        assert!(record_request(Some("http://localhost:8080"), None)
+            .endpoint()
+            .is_err());
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let local = Settings::default();
+        let exposed = Settings {
+            server_host: "0.0.0.0".to_string(),
+            ..Settings::default()
+        };
+
+        let req = TestRequest::get().uri("/api/records").to_http_request();
+        assert!(is_authorized(&req, &local));
+        assert!(!is_authorized(&req, &exposed));
+
+        let req = TestRequest::get()
+            .uri("/api/records")
+            .insert_header((header::ORIGIN, "http://example.com"))
+            .to_http_request();
+        assert!(!is_authorized(&req, &local));
+
+        let with_token = Settings {
+            api_token: Some("secret".to_string()),
+            ..exposed
+        };
+        assert!(!is_authorized(&req, &with_token));
+        let req = TestRequest::get()
+            .uri("/api/records")
+            .insert_header((header::AUTHORIZATION, "Bearer secret"))
+            .to_http_request();
+        assert!(is_authorized(&req, &with_token));
+    }
+
+    #[actix_web::test]
+    async fn test_recordings_routes() {
+        let manager = web::Data::from(Arc::new(RecorderManager::new()));
+        let app = init_service(
+            App::new().service(web::scope("/api").app_data(manager).configure(config)),
+        )
+        .await;
+
+        let req = TestRequest::get().uri("/api/recordings").to_request();
+        let recordings: Vec<serde_json::Value> = call_and_read_body_json(&app, req).await;
+        assert!(recordings.is_empty());
+
+        let req = TestRequest::delete()
+            .uri("/api/recordings/KR/6654667050")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+}
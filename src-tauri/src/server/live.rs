@@ -0,0 +1,92 @@
+use crate::server::timeline::Timeline;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type LiveKey = (String, String);
+
+/// Where a live broadcast currently is in the recorded game.
+#[derive(Debug, Clone, Copy)]
+pub struct LivePosition {
+    pub start_chunk_id: Option<u32>,
+    pub elapsed: Duration,
+}
+
+impl LivePosition {
+    /// Chunk reached after `elapsed` when every chunk is released once the
+    /// previous one has been available for its recorded duration, and for how
+    /// many milliseconds it has been available.
+    pub fn chunk(
+        &self,
+        first_chunk_id: u32,
+        last_chunk_id: u32,
+        timeline: &Timeline,
+    ) -> (u32, u64) {
+        let mut chunk_id = self
+            .start_chunk_id
+            .unwrap_or(first_chunk_id)
+            .min(last_chunk_id);
+        let mut available_since = self.elapsed.as_millis() as u64;
+
+        while chunk_id < last_chunk_id {
+            let duration = timeline.duration(chunk_id + 1).max(1) as u64;
+            if available_since < duration {
+                break;
+            }
+            available_since -= duration;
+            chunk_id += 1;
+        }
+
+        (chunk_id, available_since)
+    }
+}
+
+struct LiveSession {
+    started_at: Instant,
+    start_chunk_id: Option<u32>,
+}
+
+/// Recorded games currently rebroadcast "as live" by the replay server,
+/// keyed by (platform_id, game_id).
+#[derive(Default)]
+pub struct LiveSessions {
+    sessions: Mutex<HashMap<LiveKey, LiveSession>>,
+}
+
+impl LiveSessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start, or restart, the playback clock of a game. Without a
+    /// `start_chunk_id` the broadcast begins with the game start chunk.
+    pub fn start(&self, platform_id: String, game_id: String, start_chunk_id: Option<u32>) {
+        self.sessions.lock().unwrap().insert(
+            (platform_id, game_id),
+            LiveSession {
+                started_at: Instant::now(),
+                start_chunk_id,
+            },
+        );
+    }
+
+    pub fn stop(&self, platform_id: &str, game_id: &str) -> bool {
+        self.sessions
+            .lock()
+            .unwrap()
+            .remove(&(platform_id.to_string(), game_id.to_string()))
+            .is_some()
+    }
+
+    pub fn position(&self, platform_id: &str, game_id: &str) -> Option<LivePosition> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get(&(platform_id.to_string(), game_id.to_string()))
+            .map(|session| LivePosition {
+                start_chunk_id: session.start_chunk_id,
+                elapsed: session.started_at.elapsed(),
+            })
+    }
+}
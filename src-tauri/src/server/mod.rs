@@ -1 +1,4 @@
+pub mod api;
+pub mod live;
 pub mod spectator;
+pub mod timeline;
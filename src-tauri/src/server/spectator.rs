@@ -1,16 +1,22 @@
-use std::sync::Mutex;
-
-use actix_web::{get, middleware, web, App, Error, HttpResponse, HttpServer};
-use tauri::AppHandle;
-use tokio::fs::File;
-use tokio::io::AsyncReadExt;
+use actix_web::{error, get, middleware, web, App, Error, HttpResponse, HttpServer};
+use log::{info, warn};
 
+use crate::archive::error::ArchiveError;
+use crate::archive::storage::{RecordStorage, StorageCache};
+use crate::db;
+use crate::models::record::{Record, RecordStatus};
+use crate::models::record_chunk::RecordChunk;
 use crate::queries;
 use crate::recorder::api::models::ChunkInfo;
+use crate::recorder::manager::RecorderManager;
+use crate::server::api;
+use crate::server::live::{LivePosition, LiveSessions};
+use crate::server::timeline::Timeline;
+use crate::settings;
 
-struct TauriAppState {
-    app: Mutex<AppHandle>,
-}
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 
 #[get("/version")]
 async fn version() -> HttpResponse {
@@ -18,80 +24,256 @@ async fn version() -> HttpResponse {
 }
 
 #[get("/getGameMetaData/{platform_id}/{game_id}/{_}/token")]
-async fn get_game_meta_data(path_info: web::Path<(String, String, String)>) -> HttpResponse {
-    let (_platform_id, game_id, _unamed) = path_info.into_inner();
+async fn get_game_meta_data(
+    live_sessions: web::Data<LiveSessions>,
+    path_info: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (platform_id, game_id, _unamed) = path_info.into_inner();
+
+    if let Some(record) = find_record(game_id.clone()).await? {
+        let metadata = match live_sessions.position(&platform_id, &game_id) {
+            Some(position) => {
+                let chunks = find_record_chunks(record.id.clone()).await?;
+                live_game_meta_data(&record, chunks, position)
+            }
+            None => Some(record.metadata),
+        };
 
-    if let Some(record) = queries::get_record(game_id) {
-        HttpResponse::Ok()
-            .content_type("application/json")
-            .body(record.metadata)
+        match metadata {
+            Some(metadata) => Ok(HttpResponse::Ok()
+                .content_type("application/json")
+                .body(metadata)),
+            None => Ok(HttpResponse::NotFound().finish()),
+        }
     } else {
-        HttpResponse::NotFound().finish()
+        Ok(HttpResponse::NotFound().finish())
     }
 }
 
+/// Metadata of a game rebroadcast as live: it must look like the game is still
+/// running until the playback clock reaches its end.
+fn live_game_meta_data(
+    record: &Record,
+    chunks: Vec<RecordChunk>,
+    position: LivePosition,
+) -> Option<String> {
+    let mut metadata = record.game_meta_data()?;
+    let chunk_info = last_chunk_info(record, chunks, Some(position))?;
+
+    if chunk_info.end_game_chunk_id == 0 {
+        metadata.game_ended = false;
+        metadata.end_game_chunk_id = -1;
+        metadata.end_game_key_frame_id = -1;
+    }
+    metadata.last_chunk_id = chunk_info.chunk_id;
+    metadata.last_key_frame_id = chunk_info.key_frame_id;
+
+    serde_json::to_string(&metadata).ok()
+}
+
 #[get("/getLastChunkInfo/{platform_id}/{game_id}/{_}/token")]
-async fn get_last_chunk_info(path_info: web::Path<(String, String, String)>) -> HttpResponse {
-    let (_platform_id, game_id, _unamed) = path_info.into_inner();
+async fn get_last_chunk_info(
+    live_sessions: web::Data<LiveSessions>,
+    path_info: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (platform_id, game_id, _unamed) = path_info.into_inner();
+    let position = live_sessions.position(&platform_id, &game_id);
 
-    let data = ChunkInfo {
-        chunk_id: 1,
-        available_since: 30000,
-        next_available_chunk: 10000,
-        key_frame_id: 1,
-        next_chunk_id: 2,
-        end_startup_chunk_id: 1,
-        start_game_chunk_id: 2,
-        end_game_chunk_id: 0,
-        duration: 3000,
+    let Some(record) = find_record(game_id).await? else {
+        return Ok(HttpResponse::NotFound().finish());
     };
+    let chunks = find_record_chunks(record.id.clone()).await?;
+
+    match last_chunk_info(&record, chunks, position) {
+        Some(data) => Ok(HttpResponse::Ok().json(data)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Describe the last chunk a replay can reach. Without a live position this
+/// is the end of the game once the recording is complete, or the last chunk
+/// downloaded so far. With one, chunks are released at the recorded cadence.
+fn last_chunk_info(
+    record: &Record,
+    chunks: Vec<RecordChunk>,
+    live_position: Option<LivePosition>,
+) -> Option<ChunkInfo> {
+    let metadata = record.game_meta_data()?;
+    let last_chunk_id = record.last_contiguous_game_data_chunk();
+    if last_chunk_id == 0 {
+        return None;
+    }
 
-    HttpResponse::Ok().json(data)
+    let timeline = Timeline::new(&metadata, chunks);
+    let end_game_chunk_id = if metadata.end_game_chunk_id > 0 {
+        metadata.end_game_chunk_id as u32
+    } else if record.record_status() == Some(RecordStatus::Complete) {
+        last_chunk_id
+    } else {
+        0
+    };
+    let (chunk_id, available_since) = match live_position {
+        Some(position) => position.chunk(metadata.start_game_chunk_id, last_chunk_id, &timeline),
+        None => (last_chunk_id, timeline.duration(last_chunk_id) as u64),
+    };
+    let key_frame_id = timeline
+        .key_frame_for_chunk(chunk_id)
+        .min(record.last_contiguous_keyframe());
+    let ended = end_game_chunk_id != 0 && chunk_id >= end_game_chunk_id;
+
+    Some(ChunkInfo {
+        chunk_id,
+        available_since,
+        next_available_chunk: if ended {
+            0
+        } else {
+            timeline
+                .duration(chunk_id + 1)
+                .saturating_sub(available_since as u32)
+        },
+        key_frame_id,
+        next_chunk_id: timeline.next_chunk_for_key_frame(key_frame_id),
+        end_startup_chunk_id: metadata.end_startup_chunk_id,
+        start_game_chunk_id: metadata.start_game_chunk_id,
+        // A live game only announces its end once it is reached
+        end_game_chunk_id: if live_position.is_none() || ended {
+            end_game_chunk_id
+        } else {
+            0
+        },
+        duration: timeline.duration(chunk_id),
+    })
 }
 
 #[get("/getGameDataChunk/{platform_id}/{game_id}/{chunk_id}/token")]
 async fn get_game_data_chunk(
+    storage_cache: web::Data<StorageCache>,
     path_info: web::Path<(String, String, u32)>,
 ) -> Result<HttpResponse, Error> {
     let (_platform_id, game_id, chunk_id) = path_info.into_inner();
 
-    if let Some(record) = queries::get_record(game_id) {
-        let path = format!("{}/game_data_chunks/{}", record.storage_path, chunk_id);
-        let mut file = File::open(path).await?;
-        let mut content = Vec::new();
-        file.read_to_end(&mut content).await?;
-
-        Ok(HttpResponse::Ok().body(content))
+    if let Some(record) = find_record(game_id).await? {
+        stored_file(storage_cache, record, move |storage| {
+            storage.game_data_chunk(chunk_id)
+        })
+        .await
     } else {
         Ok(HttpResponse::NotFound().finish())
     }
 }
 
 #[get("/getKeyFrame/{platform_id}/{game_id}/{keyframe_id}/token")]
-async fn get_key_frame(path_info: web::Path<(String, String, u32)>) -> Result<HttpResponse, Error> {
+async fn get_key_frame(
+    storage_cache: web::Data<StorageCache>,
+    path_info: web::Path<(String, String, u32)>,
+) -> Result<HttpResponse, Error> {
     let (_platform_id, game_id, keyframe_id) = path_info.into_inner();
 
-    if let Some(record) = queries::get_record(game_id) {
-        let path = format!("{}/keyframes/{}", record.storage_path, keyframe_id);
-        let mut file = File::open(path).await?;
-        let mut content = Vec::new();
-        file.read_to_end(&mut content).await?;
+    if let Some(record) = find_record(game_id).await? {
+        stored_file(storage_cache, record, move |storage| {
+            storage.keyframe(keyframe_id)
+        })
+        .await
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
 
-        Ok(HttpResponse::Ok().body(content))
+#[get("/endOfGameStats/{platform_id}/{game_id}/{_}")]
+async fn get_end_of_game_stats(
+    storage_cache: web::Data<StorageCache>,
+    path_info: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (_platform_id, game_id, _unamed) = path_info.into_inner();
+
+    if let Some(record) = find_record(game_id).await? {
+        stored_file(storage_cache, record, RecordStorage::end_of_game_stats).await
     } else {
         Ok(HttpResponse::NotFound().finish())
     }
 }
 
+/// Record of the game, queried on the blocking thread pool.
+async fn find_record(game_id: String) -> Result<Option<Record>, Error> {
+    db::blocking(move || queries::get_record(game_id))
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
+
+async fn find_record_chunks(record_id: String) -> Result<Vec<RecordChunk>, Error> {
+    db::blocking(move || queries::get_record_chunks(&record_id))
+        .await
+        .map_err(error::ErrorInternalServerError)
+}
+
+/// Respond with a file of the record, read from its directory or its archive
+/// on the blocking thread pool.
+async fn stored_file<F>(
+    storage_cache: web::Data<StorageCache>,
+    record: Record,
+    read: F,
+) -> Result<HttpResponse, Error>
+where
+    F: FnOnce(&RecordStorage) -> Result<Option<Vec<u8>>, ArchiveError> + Send + 'static,
+{
+    let content = web::block(move || read(&storage_cache.open(&record)?))
+        .await?
+        .map_err(error::ErrorInternalServerError)?;
+
+    match content {
+        Some(content) => Ok(HttpResponse::Ok().body(content)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Address the replay server actually listens on, which differs from the
+/// settings when the preferred port was taken.
+#[derive(Default)]
+pub struct ServerAddress {
+    address: Mutex<Option<SocketAddr>>,
+}
+
+impl ServerAddress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> Option<SocketAddr> {
+        *self.address.lock().unwrap()
+    }
+
+    fn set(&self, address: Option<SocketAddr>) {
+        *self.address.lock().unwrap() = address;
+    }
+}
+
+/// Run the replay server until it is stopped. With a `manager` the control
+/// API is mounted under `/api` as well, unless the server listens beyond
+/// loopback without an `api_token`.
 #[actix_web::main]
-pub async fn init(app: AppHandle) -> std::io::Result<()> {
-    let tauri_app = web::Data::new(TauriAppState {
-        app: Mutex::new(app),
-    });
-
-    HttpServer::new(move || {
-        App::new()
-            .app_data(tauri_app.clone())
+pub async fn init(
+    live_sessions: Arc<LiveSessions>,
+    server_address: Arc<ServerAddress>,
+    manager: Option<Arc<RecorderManager>>,
+) -> std::io::Result<()> {
+    let settings = settings::current();
+    let live_sessions = web::Data::from(live_sessions);
+    let storage_cache = web::Data::new(StorageCache::new());
+    // Without a token the control API is not exposed beyond this machine
+    let manager = match manager {
+        Some(_) if settings.api_token.is_none() && !settings.is_loopback_server() => {
+            warn!(
+                "Not serving the control API on {} without an api_token",
+                settings.server_host
+            );
+            None
+        }
+        manager => manager.map(web::Data::from),
+    };
+    let app = move || {
+        let app = App::new()
+            .app_data(live_sessions.clone())
+            .app_data(storage_cache.clone())
             .wrap(middleware::Logger::default())
             .service(
                 web::scope("/observer-mode/rest/consumer")
@@ -99,10 +281,178 @@ pub async fn init(app: AppHandle) -> std::io::Result<()> {
                     .service(get_last_chunk_info)
                     .service(get_game_meta_data)
                     .service(get_game_data_chunk)
-                    .service(get_key_frame),
-            )
-    })
-    .bind(("127.0.0.1", 4875))?
-    .run()
-    .await
+                    .service(get_key_frame)
+                    .service(get_end_of_game_stats),
+            );
+
+        match &manager {
+            Some(manager) => app.service(
+                web::scope("/api")
+                    .app_data(manager.clone())
+                    .configure(api::config),
+            ),
+            None => app,
+        }
+    };
+
+    let host = settings.server_host.as_str();
+    let server = match HttpServer::new(app.clone()).bind((host, settings.server_port)) {
+        Ok(server) => server,
+        Err(error) if error.kind() == ErrorKind::AddrInUse => {
+            warn!(
+                "Port {} is already in use, letting the system pick a free one",
+                settings.server_port
+            );
+            HttpServer::new(app).bind((host, 0))?
+        }
+        Err(error) => return Err(error),
+    };
+
+    let address = server.addrs().first().copied();
+    if let Some(address) = address {
+        info!("Replay server listening on {}", address);
+    }
+    server_address.set(address);
+
+    let result = server.run().await;
+    server_address.set(None);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::api::models::GameMetaData;
+    use crate::test_utils::METADATA;
+
+    use std::time::Duration;
+
+    /// A game with the startup chunk 1, starting at chunk 3, and `chunks`
+    /// chunks and `keyframes` keyframes stored. Without `end_game_chunk_id`
+    /// the game is still being recorded.
+    fn record(chunks: u32, keyframes: u32, end_game_chunk_id: Option<u32>) -> Record {
+        let mut metadata: GameMetaData = serde_json::from_str(METADATA).unwrap();
+        metadata.end_startup_chunk_id = 1;
+        metadata.start_game_chunk_id = 3;
+        metadata.pending_available_chunk_info.clear();
+        metadata.pending_available_key_frame_info.clear();
+        metadata.end_game_chunk_id = end_game_chunk_id.map_or(-1, |id| id as i32);
+        let status = match end_game_chunk_id {
+            Some(_) => RecordStatus::Complete,
+            None => RecordStatus::Recording,
+        };
+
+        Record {
+            id: "record".to_string(),
+            version: "13.16.526.3432".to_string(),
+            base_url: "http://spectator.kr.lol.pvp.net:8080".to_string(),
+            platform_id: "KR".to_string(),
+            game_id: "6654667050".to_string(),
+            encryption_key: "JtjqZUJMDnvmnEbpJjqmwNdCNsBLfRZw".to_string(),
+            metadata: serde_json::to_string(&metadata).unwrap(),
+            keyframes: serde_json::to_string(&(1..=keyframes).collect::<Vec<_>>()).unwrap(),
+            game_data_chunks: serde_json::to_string(&(1..=chunks).collect::<Vec<_>>()).unwrap(),
+            storage_path: String::new(),
+            created_at: chrono::Utc::now().naive_utc(),
+            status: status.to_string(),
+            status_reason: None,
+        }
+    }
+
+    fn live(start_chunk_id: Option<u32>, elapsed_secs: u64) -> Option<LivePosition> {
+        Some(LivePosition {
+            start_chunk_id,
+            elapsed: Duration::from_secs(elapsed_secs),
+        })
+    }
+
+    #[test]
+    fn test_last_chunk_info_without_chunks() {
+        assert!(last_chunk_info(&record(0, 0, None), Vec::new(), None).is_none());
+    }
+
+    #[test]
+    fn test_last_chunk_info_of_recorded_game() {
+        let info = last_chunk_info(&record(10, 4, Some(10)), Vec::new(), None).unwrap();
+
+        assert_eq!(info.chunk_id, 10);
+        assert_eq!(info.end_game_chunk_id, 10);
+        assert_eq!(info.next_available_chunk, 0);
+        assert_eq!(info.available_since, 30000);
+        assert_eq!(info.key_frame_id, 4);
+        assert_eq!(info.next_chunk_id, 9);
+        assert_eq!(info.start_game_chunk_id, 3);
+        assert_eq!(info.end_startup_chunk_id, 1);
+    }
+
+    #[test]
+    fn test_last_chunk_info_of_game_being_recorded() {
+        let info = last_chunk_info(&record(6, 2, None), Vec::new(), None).unwrap();
+
+        assert_eq!(info.chunk_id, 6);
+        assert_eq!(info.end_game_chunk_id, 0);
+        assert_eq!(info.key_frame_id, 2);
+        assert_eq!(info.next_chunk_id, 5);
+    }
+
+    #[test]
+    fn test_last_chunk_info_follows_live_position() {
+        let record = record(10, 4, Some(10));
+
+        // Start of the game
+        let info = last_chunk_info(&record, Vec::new(), live(None, 0)).unwrap();
+        assert_eq!(info.chunk_id, 3);
+        assert_eq!(info.available_since, 0);
+        assert_eq!(info.next_available_chunk, 30000);
+        assert_eq!(info.key_frame_id, 1);
+        assert_eq!(info.next_chunk_id, 3);
+        assert_eq!(info.end_game_chunk_id, 0);
+
+        // Two chunks and a half later
+        let info = last_chunk_info(&record, Vec::new(), live(None, 75)).unwrap();
+        assert_eq!(info.chunk_id, 5);
+        assert_eq!(info.available_since, 15000);
+        assert_eq!(info.next_available_chunk, 15000);
+        assert_eq!(info.key_frame_id, 2);
+        assert_eq!(info.end_game_chunk_id, 0);
+
+        // The end of the game is only announced once reached
+        let info = last_chunk_info(&record, Vec::new(), live(None, 3600)).unwrap();
+        assert_eq!(info.chunk_id, 10);
+        assert_eq!(info.end_game_chunk_id, 10);
+        assert_eq!(info.next_available_chunk, 0);
+        assert_eq!(info.key_frame_id, 4);
+    }
+
+    #[test]
+    fn test_live_position_is_clamped_to_stored_chunks() {
+        let info = last_chunk_info(&record(6, 2, None), Vec::new(), live(Some(50), 0)).unwrap();
+
+        assert_eq!(info.chunk_id, 6);
+        assert_eq!(info.end_game_chunk_id, 0);
+        assert_eq!(info.key_frame_id, 2);
+    }
+
+    #[test]
+    fn test_key_frame_before_the_first_one_is_stored() {
+        let info = last_chunk_info(&record(2, 0, None), Vec::new(), live(Some(1), 0)).unwrap();
+
+        assert_eq!(info.chunk_id, 1);
+        assert_eq!(info.key_frame_id, 0);
+        assert_eq!(info.next_chunk_id, 1);
+    }
+
+    #[test]
+    fn test_next_chunk_id_uses_stored_keyframe_mapping() {
+        let chunks = vec![RecordChunk {
+            key_frame_id: Some(4),
+            next_chunk_id: Some(8),
+            ..RecordChunk::new("record".to_string(), 10)
+        }];
+        let info = last_chunk_info(&record(10, 4, Some(10)), chunks, None).unwrap();
+
+        assert_eq!(info.key_frame_id, 4);
+        assert_eq!(info.next_chunk_id, 8);
+    }
 }
@@ -0,0 +1,74 @@
+use crate::models::record_chunk::RecordChunk;
+use crate::recorder::api::models::GameMetaData;
+
+use std::collections::HashMap;
+
+/// Chunk durations and keyframe relationships of a recorded game, as stored
+/// while recording. Chunks the spectator API told us nothing about fall back
+/// to the game chunk interval and the usual two chunks per keyframe.
+pub struct Timeline {
+    chunk_time_interval: u32,
+    key_frame_chunk_offset: u32,
+    chunks: HashMap<u32, RecordChunk>,
+}
+
+impl Timeline {
+    pub fn new(metadata: &GameMetaData, chunks: Vec<RecordChunk>) -> Self {
+        let chunks: HashMap<u32, RecordChunk> = chunks
+            .into_iter()
+            .map(|chunk| (chunk.chunk_id as u32, chunk))
+            .collect();
+
+        // Keyframes are taken every two chunks, a known keyframe tells us the
+        // offset between both sequences
+        let key_frame_chunk_offset = chunks
+            .values()
+            .filter_map(|chunk| Some((chunk.key_frame_id?, chunk.next_chunk_id?)))
+            .chain(
+                metadata
+                    .pending_available_key_frame_info
+                    .iter()
+                    .map(|info| (info.key_frame_id as i32, info.next_chunk_id as i32)),
+            )
+            .map(|(key_frame_id, next_chunk_id)| next_chunk_id - key_frame_id * 2)
+            .find(|offset| *offset >= 0)
+            .unwrap_or(1) as u32;
+
+        Timeline {
+            chunk_time_interval: metadata.chunk_time_interval,
+            key_frame_chunk_offset,
+            chunks,
+        }
+    }
+
+    /// Duration of a chunk in milliseconds.
+    pub fn duration(&self, chunk_id: u32) -> u32 {
+        self.chunks
+            .get(&chunk_id)
+            .and_then(|chunk| chunk.duration)
+            .map(|duration| duration as u32)
+            .unwrap_or(self.chunk_time_interval)
+    }
+
+    /// Latest keyframe available once `chunk_id` is.
+    pub fn key_frame_for_chunk(&self, chunk_id: u32) -> u32 {
+        match self
+            .chunks
+            .get(&chunk_id)
+            .and_then(|chunk| chunk.key_frame_id)
+        {
+            Some(key_frame_id) => key_frame_id as u32,
+            None => (chunk_id.saturating_sub(self.key_frame_chunk_offset) / 2).max(1),
+        }
+    }
+
+    /// Chunk to play after loading `key_frame_id`, used to seek in the game.
+    pub fn next_chunk_for_key_frame(&self, key_frame_id: u32) -> u32 {
+        self.chunks
+            .values()
+            .filter(|chunk| chunk.key_frame_id == Some(key_frame_id as i32))
+            .find_map(|chunk| chunk.next_chunk_id)
+            .map(|next_chunk_id| next_chunk_id as u32)
+            .unwrap_or(key_frame_id * 2 + self.key_frame_chunk_offset)
+    }
+}
@@ -0,0 +1,222 @@
+use crate::recorder::api::models::Region;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("IO error occurred: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("invalid settings file: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("storage path {0} is not writable")]
+    StorageNotWritable(PathBuf),
+
+    #[error("database path {0} is not a writable file location")]
+    DatabaseNotWritable(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Directory holding one sub directory per recorded game.
+    pub storage_path: PathBuf,
+    pub database_path: PathBuf,
+    pub default_region: Region,
+    /// Address the replay server binds to.
+    pub server_host: String,
+    /// Preferred replay server port, a free one is used when it is taken.
+    pub server_port: u16,
+    /// Token the control API requires as a bearer token. When unset, the API
+    /// is only served on a loopback `server_host` and requests coming from a
+    /// browser are refused.
+    pub api_token: Option<String>,
+    pub http: HttpSettings,
+}
+
+/// Client used for every request to the spectator API.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct HttpSettings {
+    pub connect_timeout_secs: u64,
+    /// Time allowed for a whole request, body included.
+    pub timeout_secs: u64,
+    pub user_agent: String,
+    /// Chunks and keyframes a recording downloads at once.
+    pub max_concurrent_downloads: usize,
+}
+
+impl Default for HttpSettings {
+    fn default() -> Self {
+        HttpSettings {
+            connect_timeout_secs: 10,
+            timeout_secs: 30,
+            user_agent: format!("pyke-director/{}", env!("CARGO_PKG_VERSION")),
+            max_concurrent_downloads: 4,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let data_dir = dirs::data_dir().or_else(dirs::home_dir).unwrap_or_default();
+
+        Settings {
+            storage_path: data_dir.join("pyke-director").join("records"),
+            database_path: get_config_dir().join("database.sqlite"),
+            default_region: Region::EUW1,
+            server_host: "127.0.0.1".to_string(),
+            server_port: 4875,
+            api_token: None,
+            http: HttpSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Read the settings file, falling back to the defaults when there is none.
+    pub fn load() -> Result<Self, SettingsError> {
+        let path = get_settings_file_path();
+        if !path.exists() {
+            return Ok(Settings::default());
+        }
+
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self) -> Result<(), SettingsError> {
+        let path = get_settings_file_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Whether the replay server only accepts connections from this machine.
+    pub fn is_loopback_server(&self) -> bool {
+        match self.server_host.parse::<IpAddr>() {
+            Ok(address) => address.is_loopback(),
+            Err(_) => self.server_host.eq_ignore_ascii_case("localhost"),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        check_writable(&self.storage_path).map_err(|error| {
+            debug!(
+                "Storage path {} check failed: {}",
+                self.storage_path.display(),
+                error
+            );
+            SettingsError::StorageNotWritable(self.storage_path.clone())
+        })?;
+
+        // The database file is created in its directory when missing
+        let database_dir = match self.database_path.parent() {
+            Some(dir) if !self.database_path.is_dir() => dir,
+            _ => {
+                return Err(SettingsError::DatabaseNotWritable(
+                    self.database_path.clone(),
+                ))
+            }
+        };
+        check_writable(database_dir).map_err(|error| {
+            debug!(
+                "Database path {} check failed: {}",
+                self.database_path.display(),
+                error
+            );
+            SettingsError::DatabaseNotWritable(self.database_path.clone())
+        })
+    }
+}
+
+/// Application directory for the settings, regions file and default database.
+pub fn get_config_dir() -> PathBuf {
+    dirs::config_dir()
+        .or_else(|| dirs::home_dir().map(|home_dir| home_dir.join(".config")))
+        .unwrap_or_default()
+        .join("pyke-director")
+}
+
+fn get_settings_file_path() -> PathBuf {
+    get_config_dir().join("settings.json")
+}
+
+/// Create the directory at `path` if needed and check a file can be written
+/// in it.
+fn check_writable(path: &Path) -> Result<(), io::Error> {
+    let probe = path.join(".pyke-director-write-test");
+
+    fs::create_dir_all(path)
+        .and_then(|_| fs::write(&probe, b""))
+        .and_then(|_| fs::remove_file(&probe))
+}
+
+fn settings() -> &'static RwLock<Settings> {
+    static SETTINGS: OnceLock<RwLock<Settings>> = OnceLock::new();
+
+    SETTINGS.get_or_init(|| {
+        RwLock::new(Settings::load().unwrap_or_else(|error| {
+            debug!("Could not load settings, using defaults: {}", error);
+            Settings::default()
+        }))
+    })
+}
+
+/// Settings currently in use.
+pub fn current() -> Settings {
+    settings().read().unwrap().clone()
+}
+
+/// Validate, persist and apply new settings.
+pub fn update(new_settings: Settings) -> Result<Settings, SettingsError> {
+    new_settings.validate()?;
+    new_settings.save()?;
+    *settings().write().unwrap() = new_settings.clone();
+
+    Ok(new_settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_dir;
+
+    #[test]
+    fn test_validate_database_path() {
+        let dir = test_dir("settings");
+        let settings = Settings {
+            storage_path: dir.join("storage"),
+            database_path: dir.join("db/pyke-director.db"),
+            ..Settings::default()
+        };
+        assert!(settings.validate().is_ok());
+
+        // A directory, or a path whose parent is a file, cannot hold the database
+        for database_path in [dir.join("storage"), dir.join("file/pyke-director.db")] {
+            fs::write(dir.join("file"), b"").unwrap();
+            let settings = Settings {
+                database_path,
+                ..settings.clone()
+            };
+            assert!(matches!(
+                settings.validate(),
+                Err(SettingsError::DatabaseNotWritable(_))
+            ));
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,43 @@
+//! Fixtures shared by the tests of several modules.
+
+use crate::models::record::Record;
+
+use uuid::Uuid;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Game metadata of the record built by `stored_record`.
+pub const METADATA: &str = r#"{"gameKey":{"gameId":6654667050,"platformId":"KR"},"gameServerAddress":"","port":0,"encryptionKey":"","chunkTimeInterval":30000,"startTime":"Aug 15, 2023 8:01:42 PM","gameEnded":true,"lastChunkId":2,"lastKeyFrameId":1,"endStartupChunkId":1,"delayTime":180000,"pendingAvailableChunkInfo":[{"chunkId":2,"duration":29987,"receivedTime":"Aug 15, 2023 8:02:12 PM"}],"pendingAvailableKeyFrameInfo":[{"keyFrameId":1,"receivedTime":"Aug 15, 2023 8:02:12 PM","nextChunkId":2}],"keyFrameTimeInterval":60000,"decodedEncryptionKey":"","startGameChunkId":2,"gameLength":0,"clientAddedLag":0,"clientBackFetchingEnabled":false,"clientBackFetchingFreq":1000,"interestScore":3325,"featuredGame":false,"createTime":"Aug 15, 2023 8:01:55 PM","endGameChunkId":2,"endGameKeyFrameId":1}"#;
+
+/// New empty directory under the system temporary directory.
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("pyke-director-{}-{}", name, Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Record of a game with two chunks and a keyframe stored in `storage_path`.
+pub fn stored_record(storage_path: &Path) -> Record {
+    fs::create_dir_all(storage_path.join("game_data_chunks")).unwrap();
+    fs::create_dir_all(storage_path.join("keyframes")).unwrap();
+    fs::write(storage_path.join("game_data_chunks/1"), b"chunk 1").unwrap();
+    fs::write(storage_path.join("game_data_chunks/2"), b"chunk two").unwrap();
+    fs::write(storage_path.join("keyframes/1"), b"keyframe").unwrap();
+
+    Record {
+        id: "record".to_string(),
+        version: "13.16.526.3432".to_string(),
+        base_url: "http://spectator.kr.lol.pvp.net:8080".to_string(),
+        platform_id: "KR".to_string(),
+        game_id: "6654667050".to_string(),
+        encryption_key: "JtjqZUJMDnvmnEbpJjqmwNdCNsBLfRZw".to_string(),
+        metadata: METADATA.to_string(),
+        keyframes: "[1]".to_string(),
+        game_data_chunks: "[1,2]".to_string(),
+        storage_path: storage_path.display().to_string(),
+        created_at: chrono::Utc::now().naive_utc(),
+        status: "complete".to_string(),
+        status_reason: None,
+    }
+}
@@ -0,0 +1,33 @@
+use crate::archive::error::ArchiveError;
+use crate::db::DbError;
+use crate::decoder::error::DecodeError;
+
+use thiserror::Error;
+use tokio::task::JoinError;
+
+#[derive(Error, Debug)]
+pub enum VerifyError {
+    #[error("IO error occurred: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("could not read the recorded files: {0}")]
+    Storage(#[from] ArchiveError),
+
+    #[error("could not decode the recorded files: {0}")]
+    Decode(#[from] DecodeError),
+
+    #[error("could not access the database: {0}")]
+    Database(#[from] DbError),
+
+    #[error("verification task failed: {0}")]
+    Blocking(#[from] JoinError),
+
+    #[error("no record for game {0}")]
+    RecordNotFound(String),
+
+    #[error("game {0} is still being recorded")]
+    StillRecording(String),
+
+    #[error("game {0} is packed in an archive, its files cannot be repaired")]
+    Packed(String),
+}
@@ -0,0 +1,325 @@
+pub mod error;
+
+use crate::archive::error::ArchiveError;
+use crate::archive::storage::RecordStorage;
+use crate::decoder::time_index;
+use crate::decoder::ChunkDecoder;
+use crate::models::record::{Record, RecordStatus};
+use crate::queries;
+use crate::recorder::api::endpoints;
+use crate::recorder::api::models::SpectatorEndpoint;
+use crate::recorder::models::store_file;
+use error::VerifyError;
+
+use log::debug;
+use serde::Serialize;
+use tokio::task::spawn_blocking;
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+/// What is wrong with the stored game data chunks or keyframes of a record.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct MediaReport {
+    /// Last id the record should hold, the end game one once the game is
+    /// over, the last one announced by the metadata otherwise.
+    pub last_id: u32,
+    /// Ids from 1 to `last_id` without a stored file.
+    pub missing: Vec<u32>,
+    /// Files left empty, usually by an interrupted write.
+    pub empty: Vec<u32>,
+    /// Files that do not decrypt or decompress.
+    pub undecodable: Vec<u32>,
+    /// Stored files the database does not list.
+    pub not_in_database: Vec<u32>,
+    /// Ids the database lists without a stored file.
+    pub not_on_disk: Vec<u32>,
+    /// Ids with a file that decodes.
+    #[serde(skip)]
+    pub stored: BTreeSet<u32>,
+}
+
+impl MediaReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+            && self.empty.is_empty()
+            && self.undecodable.is_empty()
+            && self.not_in_database.is_empty()
+            && self.not_on_disk.is_empty()
+    }
+
+    /// Ids whose file has to be downloaded again.
+    pub fn to_refetch(&self) -> BTreeSet<u32> {
+        self.missing
+            .iter()
+            .chain(&self.empty)
+            .chain(&self.undecodable)
+            .chain(&self.not_on_disk)
+            .copied()
+            .collect()
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct VerifyReport {
+    pub platform_id: String,
+    pub game_id: String,
+    pub game_data_chunks: MediaReport,
+    pub keyframes: MediaReport,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.game_data_chunks.is_ok() && self.keyframes.is_ok()
+    }
+}
+
+/// Outcome of a repair, with the state of the record once repaired.
+#[derive(Serialize, Debug)]
+pub struct RepairReport {
+    /// Whether the spectator API still serves the game, nothing can be
+    /// downloaded again otherwise.
+    pub available: bool,
+    pub refetched_game_data_chunks: Vec<u32>,
+    pub refetched_keyframes: Vec<u32>,
+    pub report: VerifyReport,
+}
+
+/// Check that a record holds every game data chunk and keyframe from 1 to the
+/// end of the game, that each one decodes and that the database lists exactly
+/// the stored ones.
+pub fn verify_record(platform_id: &str, game_id: &str) -> Result<VerifyReport, VerifyError> {
+    let record = queries::get_platform_record(platform_id, game_id)?
+        .ok_or_else(|| VerifyError::RecordNotFound(game_id.to_string()))?;
+
+    check_record(&record)
+}
+
+fn check_record(record: &Record) -> Result<VerifyReport, VerifyError> {
+    let decoder = ChunkDecoder::new(&record.encryption_key, &record.game_id)?;
+    let storage = RecordStorage::open(record)?;
+    let (last_chunk_id, last_keyframe_id) = match record.game_meta_data() {
+        Some(metadata) if metadata.end_game_chunk_id > 0 => (
+            metadata.end_game_chunk_id as u32,
+            metadata.end_game_key_frame_id.max(0) as u32,
+        ),
+        Some(metadata) => (metadata.last_chunk_id, metadata.last_key_frame_id),
+        None => (0, 0),
+    };
+
+    Ok(VerifyReport {
+        platform_id: record.platform_id.clone(),
+        game_id: record.game_id.clone(),
+        game_data_chunks: check_media(
+            last_chunk_id,
+            &record.game_data_chunk_ids(),
+            &decoder,
+            |chunk_id| storage.game_data_chunk(chunk_id),
+        )?,
+        keyframes: check_media(
+            last_keyframe_id,
+            &record.keyframe_ids(),
+            &decoder,
+            |keyframe_id| storage.keyframe(keyframe_id),
+        )?,
+    })
+}
+
+fn check_media<F>(
+    last_id: u32,
+    listed: &HashSet<u32>,
+    decoder: &ChunkDecoder,
+    read: F,
+) -> Result<MediaReport, ArchiveError>
+where
+    F: Fn(u32) -> Result<Option<Vec<u8>>, ArchiveError>,
+{
+    let mut report = MediaReport {
+        last_id,
+        ..Default::default()
+    };
+    let ids: BTreeSet<u32> = (1..=last_id).chain(listed.iter().copied()).collect();
+
+    for id in ids {
+        match read(id)? {
+            None => {
+                if id <= last_id {
+                    report.missing.push(id);
+                }
+                if listed.contains(&id) {
+                    report.not_on_disk.push(id);
+                }
+            }
+            Some(data) if data.is_empty() => report.empty.push(id),
+            Some(data) => {
+                if let Err(error) = decoder.decode(&data) {
+                    debug!("Could not decode {}: {}", id, error);
+                    report.undecodable.push(id);
+                    continue;
+                }
+                if !listed.contains(&id) {
+                    report.not_in_database.push(id);
+                }
+                report.stored.insert(id);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Download again what `verify_record` reports as missing or broken while the
+/// spectator API still serves the game, then make the database list the
+/// stored files.
+/// Reading and decoding the files runs on the blocking thread pool.
+pub async fn repair_record(platform_id: &str, game_id: &str) -> Result<RepairReport, VerifyError> {
+    let (record, storage_path, report) = {
+        let platform_id = platform_id.to_string();
+        let game_id = game_id.to_string();
+        spawn_blocking(move || check_repairable(&platform_id, &game_id)).await??
+    };
+    let chunk_ids = report.game_data_chunks.to_refetch();
+    let keyframe_ids = report.keyframes.to_refetch();
+    let endpoint = SpectatorEndpoint::new(record.base_url.clone(), record.platform_id.clone());
+
+    // The metadata is only served while the game can be spectated
+    let available = if chunk_ids.is_empty() && keyframe_ids.is_empty() {
+        false
+    } else {
+        match endpoints::fetch_game_meta_data(&endpoint, &record.game_id).await {
+            Ok(_) => true,
+            Err(error) => {
+                debug!("Game {} is no longer available: {}", game_id, error);
+                false
+            }
+        }
+    };
+
+    let mut refetched_game_data_chunks = Vec::new();
+    let mut refetched_keyframes = Vec::new();
+    if available {
+        let chunks_path = storage_path.join("game_data_chunks");
+        fs::create_dir_all(&chunks_path)?;
+        for chunk_id in chunk_ids {
+            match endpoints::fetch_game_data_chunk(&endpoint, &record.game_id, chunk_id).await {
+                Ok(data) => {
+                    store(chunks_path.join(chunk_id.to_string()), data).await?;
+                    refetched_game_data_chunks.push(chunk_id);
+                }
+                Err(error) => debug!("Could not refetch chunk {}: {}", chunk_id, error),
+            }
+        }
+
+        let keyframes_path = storage_path.join("keyframes");
+        fs::create_dir_all(&keyframes_path)?;
+        for keyframe_id in keyframe_ids {
+            match endpoints::fetch_keyframe(&endpoint, &record.game_id, keyframe_id).await {
+                Ok(data) => {
+                    store(keyframes_path.join(keyframe_id.to_string()), data).await?;
+                    refetched_keyframes.push(keyframe_id);
+                }
+                Err(error) => debug!("Could not refetch keyframe {}: {}", keyframe_id, error),
+            }
+        }
+    }
+
+    let reindex = !refetched_game_data_chunks.is_empty() || !refetched_keyframes.is_empty();
+    let report = spawn_blocking(move || save_repaired_record(record, reindex)).await??;
+
+    Ok(RepairReport {
+        available,
+        refetched_game_data_chunks,
+        refetched_keyframes,
+        report,
+    })
+}
+
+/// The record of the game with its files directory and what is wrong with
+/// them, when it can be repaired.
+fn check_repairable(
+    platform_id: &str,
+    game_id: &str,
+) -> Result<(Record, PathBuf, VerifyReport), VerifyError> {
+    let record = queries::get_platform_record(platform_id, game_id)?
+        .ok_or_else(|| VerifyError::RecordNotFound(game_id.to_string()))?;
+    if record.record_status() == Some(RecordStatus::Recording) {
+        return Err(VerifyError::StillRecording(game_id.to_string()));
+    }
+    let RecordStorage::Directory(storage_path) = RecordStorage::open(&record)? else {
+        return Err(VerifyError::Packed(game_id.to_string()));
+    };
+    let report = check_record(&record)?;
+
+    Ok((record, storage_path, report))
+}
+
+/// Make the database list the stored files once repaired, with `reindex` the
+/// time index is built again.
+fn save_repaired_record(mut record: Record, reindex: bool) -> Result<VerifyReport, VerifyError> {
+    let report = check_record(&record)?;
+    record.game_data_chunks = serde_json::to_string(&report.game_data_chunks.stored).unwrap();
+    record.keyframes = serde_json::to_string(&report.keyframes.stored).unwrap();
+    queries::save_record(&record)?;
+
+    if reindex {
+        if let Err(error) = time_index::index_record(&record.id) {
+            debug!("Could not index repaired record: {}", error);
+        }
+    }
+
+    // A failed or cancelled recording the repair made whole is complete
+    let report = check_record(&record)?;
+    let game_ended = record
+        .game_meta_data()
+        .is_some_and(|metadata| metadata.end_game_chunk_id > 0);
+    if report.is_ok() && game_ended && record.record_status() != Some(RecordStatus::Complete) {
+        record.status = RecordStatus::Complete.to_string();
+        record.status_reason = None;
+        queries::save_record(&record)?;
+    }
+
+    Ok(report)
+}
+
+async fn store(path: PathBuf, data: Vec<u8>) -> Result<(), VerifyError> {
+    Ok(spawn_blocking(move || store_file(&path, &data)).await??)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    const GAME_ID: &str = "40712340";
+    const ENCRYPTION_KEY: &str = "lypnv+b7NqV+3+u3jBjMgwGEeEXm5sa7";
+    const CHUNK: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/decoder/game_data_chunk"
+    ));
+
+    #[test]
+    fn test_check_media() {
+        let decoder = ChunkDecoder::new(ENCRYPTION_KEY, GAME_ID).unwrap();
+        let files: HashMap<u32, Vec<u8>> = HashMap::from([
+            (1, CHUNK.to_vec()),
+            (2, CHUNK.to_vec()),
+            (4, Vec::new()),
+            (5, CHUNK[..CHUNK.len() - 8].to_vec()),
+            (6, CHUNK.to_vec()),
+        ]);
+        let listed = HashSet::from([1, 3, 4, 5, 6, 8]);
+
+        let report = check_media(6, &listed, &decoder, |id| Ok(files.get(&id).cloned())).unwrap();
+
+        assert_eq!(report.missing, vec![3]);
+        assert_eq!(report.empty, vec![4]);
+        assert_eq!(report.undecodable, vec![5]);
+        assert_eq!(report.not_in_database, vec![2]);
+        assert_eq!(report.not_on_disk, vec![3, 8]);
+        assert_eq!(report.stored, BTreeSet::from([1, 2, 6]));
+        assert_eq!(report.to_refetch(), BTreeSet::from([3, 4, 5, 8]));
+        assert!(!report.is_ok());
+    }
+}